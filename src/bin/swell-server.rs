@@ -2,9 +2,13 @@
 //SERVER COMIT TEST
 use swell_server::database::*;
 use swell_server::filters::*;
+use swell_server::scheduler::*;
+use swell_server::view_counter::*;
+use swell_server::notifier::*;
 
 use dotenv::dotenv;
 use std::env;
+use std::time::Duration;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
@@ -14,7 +18,20 @@ async fn main() -> Result<()>{
     let database_url = env::var("DATABASE_URL").unwrap();
     let db = Database::new(&database_url).await?;
 
-    let rest_api = rest_swell(db);
+    // a user viewing a product again within the hour is not counted twice
+    let views = ViewCounter::new(Duration::from_secs(3600));
+    // websocket connections, fed by the events notified by every server instance
+    let notifier = Notifier::new();
+
+    tokio::spawn(refresh_product_scores(db.clone(), Duration::from_secs(300)));
+    tokio::spawn(flush_product_views(db.clone(), views.clone(), Duration::from_secs(30)));
+    tokio::spawn(release_due_escrows(db.clone(), Duration::from_secs(600)));
+    tokio::spawn(settle_auctions(db.clone(), Duration::from_secs(30)));
+    tokio::spawn(expire_offers(db.clone(), Duration::from_secs(300)));
+    tokio::spawn(renew_subscriptions(db.clone(), Duration::from_secs(3600)));
+    tokio::spawn(listen_events(database_url.clone(), db.clone(), notifier.clone()));
+
+    let rest_api = rest_swell(db, views, notifier);
 
     let routes = rest_api;
     warp::serve(routes)
@@ -85,9 +85,13 @@ pub async fn db_add_image(owner_id: i32, title: String, bio: String, price: i32,
 
 use sqlx::postgres::PgPool;
 use crate::models::*;
+use crate::notifier::EVENTS_CHANNEL;
+use chrono::Utc;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
+pub type PgTransaction = sqlx::Transaction<sqlx::pool::PoolConnection<sqlx::PgConnection>>;
+
 
 #[derive(Debug, Clone)]
 pub struct Database {
@@ -113,6 +117,178 @@ impl Database {
             Err(e) => Err(e)
         }
     }
+
+    // move the escrow of an order from the seller's pending quadreum to the sale recipients
+    async fn _release_escrow(tx: &mut PgTransaction, order_id: i64) -> Result<bool, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Order,
+            r#"
+                UPDATE orders SET escrow = 'RELEASED', updated_at = NOW()
+                WHERE id = $1 AND escrow IN ('HELD', 'FROZEN') RETURNING *
+            "#,
+            order_id
+        ).fetch_one(&mut *tx).await;
+        let order = match Database::_handle_optional_result(sql_res)? {
+            Some(order) => order,
+            None => return Ok(false),
+        };
+        sqlx::query!(
+            r#"UPDATE users SET quadreum_escrow = quadreum_escrow - $1 WHERE id = $2"#, order.price, order.seller_id
+        ).execute(&mut *tx).await?;
+        // the splits agreed when the order was made, not the current ones of the product
+        let splits = sqlx::query_as!(Split,
+            r#"SELECT user_id, bps, kind FROM order_splits WHERE order_id = $1"#, order.id
+        ).fetch_all(&mut *tx).await?;
+        Database::_credit_sale(tx, order.product_id, order.seller_id, order.price, splits).await?;
+        Ok(true)
+    }
+
+    /// Share the price of a sale.
+    /// The platform fee goes to the treasury, the splits share what is left
+    /// and the seller gets the remainder.
+    async fn _credit_sale(tx: &mut PgTransaction,
+                          product_id: i64,
+                          seller_id: i64,
+                          price: i64,
+                          splits: Vec<Split>) -> Result<bool, sqlx::Error> {
+        // shares are at most 10000 bps of a positive price, they can't overflow
+        let net = Database::_take_platform_fee(tx, price, Some(product_id)).await?;
+        let mut remainder = net;
+        for split in splits {
+            let amount = Amount(net).checked_bps(split.bps).unwrap_or(Amount::ZERO).0;
+            Database::_credit(tx, split.user_id, amount, "ROYALTY", Some(product_id)).await?;
+            remainder -= amount;
+        }
+        Database::_credit(tx, seller_id, remainder, "SALE", Some(product_id)).await?;
+        Ok(true)
+    }
+
+    // credit the platform fee of a payment to the treasury, returns what is left of the price
+    async fn _take_platform_fee(tx: &mut PgTransaction, price: i64, product_id: Option<i64>) -> Result<i64, sqlx::Error> {
+        let settings = sqlx::query!(
+            r#"SELECT fee_bps, treasury_id FROM platform_settings"#
+        ).fetch_one(&mut *tx).await?;
+        let fee = Amount(price).checked_bps(settings.fee_bps).unwrap_or(Amount::ZERO).0;
+        Database::_credit(tx, settings.treasury_id, fee, "FEE", product_id).await?;
+        Ok(price - fee)
+    }
+
+    // add amount to the balance of the user and record it in the history
+    async fn _credit(tx: &mut PgTransaction, user_id: i64, amount: i64, reason: &str, product_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        if amount == 0 {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"UPDATE users SET quadreum = quadreum + $1 WHERE id = $2"#, amount, user_id
+        ).execute(&mut *tx).await?;
+        sqlx::query!(
+            r#"INSERT INTO balance_history (user_id, amount, reason, product_id) VALUES ($1, $2, $3, $4)"#,
+            user_id, amount, reason, product_id
+        ).execute(&mut *tx).await?;
+        Ok(true)
+    }
+
+    // give back the quadreum held for a bid that is no longer the highest
+    async fn _release_bid(tx: &mut PgTransaction, bidder_id: i64, amount: i64, product_id: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE users SET quadreum_held = quadreum_held - $1 WHERE id = $2"#, amount, bidder_id
+        ).execute(&mut *tx).await?;
+        Database::_credit(tx, bidder_id, amount, "BID_RELEASE", Some(product_id)).await
+    }
+
+    async fn _is_blocked(tx: &mut PgTransaction, user_id: i64, other_id: i64) -> Result<bool, sqlx::Error> {
+        let blocked = sqlx::query!(
+            r#"SELECT is_blocked($1, $2) AS blocked"#, user_id, other_id
+        ).fetch_one(&mut *tx).await?.blocked;
+        Ok(blocked)
+    }
+
+    // post a message in the direct thread of the two users, returns false if one of them blocked the other
+    async fn _add_message(tx: &mut PgTransaction, sender: i64, receiver: i64, content: &str) -> Result<bool, sqlx::Error> {
+        if Database::_is_blocked(tx, sender, receiver).await? {
+            return Ok(false)
+        }
+        let thread_id = Database::_direct_thread(tx, sender, receiver).await?;
+        sqlx::query!(
+            r#"INSERT INTO messages (thread_id, sender, content) VALUES ($1, $2, $3)"#, thread_id, sender, content
+        ).execute(&mut *tx).await?;
+        Ok(true)
+    }
+
+    // the direct thread of two different users, created with its participants on their first message
+    // it is a message request for other_id if they don't follow user_id
+    async fn _direct_thread(tx: &mut PgTransaction, user_id: i64, other_id: i64) -> Result<i64, sqlx::Error> {
+        let sql_res = sqlx::query!(
+            r#"
+                INSERT INTO threads (user_low, user_high) VALUES (LEAST($1::BIGINT, $2::BIGINT), GREATEST($1::BIGINT, $2::BIGINT))
+                ON CONFLICT (user_low, user_high) DO NOTHING RETURNING id
+            "#,
+            user_id, other_id
+        ).fetch_one(&mut *tx).await;
+        if let Some(thread) = Database::_handle_optional_result(sql_res)? {
+            sqlx::query!(
+                r#"
+                    INSERT INTO thread_participant (thread_id, user_id, accepted)
+                    VALUES ($1, $2, TRUE), ($1, $3, EXISTS(SELECT 1 FROM follows WHERE followee_id = $2 AND follower_id = $3))
+                "#,
+                thread.id, user_id, other_id
+            ).execute(&mut *tx).await?;
+            return Ok(thread.id)
+        }
+        let thread = sqlx::query!(
+            r#"SELECT id FROM threads WHERE user_low = LEAST($1::BIGINT, $2::BIGINT) AND user_high = GREATEST($1::BIGINT, $2::BIGINT)"#,
+            user_id, other_id
+        ).fetch_one(&mut *tx).await?;
+        Ok(thread.id)
+    }
+
+    async fn _is_participant(tx: &mut PgTransaction, thread_id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        let participant = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM thread_participant WHERE thread_id = $1 AND user_id = $2) AS participant"#,
+            thread_id, user_id
+        ).fetch_one(&mut *tx).await?.participant;
+        Ok(participant)
+    }
+
+    // a product can be sold one more time to the buyer outside of a purchase of several products
+    async fn _is_available(tx: &mut PgTransaction, buyer_id: i64, product: &Product) -> Result<bool, sqlx::Error> {
+        if product.deleted_at.is_some() || product.seller_id == buyer_id {
+            return Ok(false)
+        }
+        let auctioned = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM auctions WHERE product_id = $1 AND status = 'OPEN') AS auctioned"#,
+            product.id
+        ).fetch_one(&mut *tx).await?.auctioned;
+        if auctioned {
+            return Ok(false)
+        }
+        match product.stock {
+            Some(stock) => Ok(stock > 0),
+            None => {
+                let owned = sqlx::query!(
+                    r#"SELECT EXISTS(SELECT 1 FROM purchases WHERE product_id = $1 AND buyer_id = $2) AS owned"#,
+                    product.id, buyer_id
+                ).fetch_one(&mut *tx).await?.owned;
+                Ok(!owned)
+            }
+        }
+    }
+
+    // debit a month of subscription and pay the creator, returns false if the subscriber can't pay
+    async fn _pay_subscription(tx: &mut PgTransaction, subscriber_id: i64, creator_id: i64, price: i64) -> Result<bool, sqlx::Error> {
+        let debited = sqlx::query!(
+            r#"UPDATE users SET quadreum = quadreum - $1 WHERE id = $2 AND quadreum >= $1"#, price, subscriber_id
+        ).execute(&mut *tx).await?;
+        if debited != 1 {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"INSERT INTO balance_history (user_id, amount, reason) VALUES ($1, $2, 'SUBSCRIPTION')"#,
+            subscriber_id, -price
+        ).execute(&mut *tx).await?;
+        let net = Database::_take_platform_fee(tx, price, None).await?;
+        Database::_credit(tx, creator_id, net, "SUBSCRIPTION", None).await?;
+        Ok(true)
+    }
 }
 
 // Users
@@ -143,7 +319,7 @@ impl Database {
         pattern.pattern.push_str("%");
         let sql_res = sqlx::query_as!(
             User,
-            r#"SELECT * from users WHERE username ILIKE $1 AND id != $2 ORDER BY username"#,
+            r#"SELECT * from users WHERE username ILIKE $1 AND id != $2 AND NOT is_blocked(id, $2) AND NOT is_treasury(id) ORDER BY username"#,
             pattern.pattern,
             id,
         ).fetch_all(&self.pool).await?;
@@ -190,15 +366,16 @@ impl Database {
         Ok(sql_res)
     }
 
+    // false if one of the users blocked the other or the followee is the treasury
     pub async fn follow(&self, followee_id: i64, follower_id: i64) -> Result<bool, sqlx::Error> {
         let sql_res = sqlx::query!(
             r#"
-                INSERT INTO follows (followee_id, follower_id) VALUES($1, $2)
+                INSERT INTO follows (followee_id, follower_id) SELECT $1, $2 WHERE NOT is_blocked($1, $2) AND NOT is_treasury($1)
             "#,
             followee_id,
             follower_id
         ).execute(&self.pool).await?;
-        Ok(true)
+        Ok(sql_res == 1)
     }
 
     pub async fn unfollow(&self, followee_id: i64, follower_id: i64) -> Result<bool, sqlx::Error> {
@@ -212,22 +389,93 @@ impl Database {
         Ok(true)
     }
 
+    // blocking removes the follows between the two users
+    // false if the blocked user doesn't exist or is already blocked
+    pub async fn block(&self, blocked_id: i64, blocker_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = sqlx::query!(
+            r#"
+                INSERT INTO blocks (blocker_id, blocked_id) SELECT $1, $2 WHERE EXISTS(SELECT 1 FROM users WHERE id = $2)
+                ON CONFLICT DO NOTHING
+            "#,
+            blocker_id,
+            blocked_id
+        ).execute(&mut tx).await?;
+        if inserted != 1 {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"
+                DELETE FROM follows WHERE (followee_id = $1 AND follower_id = $2) OR (followee_id = $2 AND follower_id = $1)
+            "#,
+            blocker_id,
+            blocked_id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn unblock(&self, blocked_id: i64, blocker_id: i64) -> Result<bool, sqlx::Error> {
+        let sql_res = sqlx::query!(
+            r#"
+                DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2
+            "#,
+            blocker_id,
+            blocked_id
+        ).execute(&self.pool).await?;
+        Ok(sql_res == 1)
+    }
+
+    pub async fn get_blocked_users(&self, id: i64) -> Result<Vec<User>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(
+            User,
+            r#"
+                SELECT users.* FROM blocks INNER JOIN users ON users.id = blocks.blocked_id
+                WHERE blocks.blocker_id = $1 ORDER BY blocks.created_at DESC
+            "#,
+            id
+        )
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(sql_res)
+    }
+
+    // medias are stored in the given order, the one at cover is the cover of the product
+    // stock is None for a product that can be sold any number of times
     pub async fn db_add_product(&self,
                                 seller_id: i64,
                                 description: String,
                                 price: i64,
                                 product_type: String,
-                                media_type: String,
-                                path: String,
-                                thumbnail_path: String) -> Result<bool, sqlx::Error> {
-        let sql_res = sqlx::query_as!(Media, r#"
-        INSERT INTO medias (path, thumbnail_path, media_type) VALUES ($1, $2, $3) RETURNING *
-        "#, path, thumbnail_path, media_type)
-            .fetch_one(&self.pool)
-            .await.unwrap();
+                                stock: Option<i64>,
+                                medias: Vec<NewMedia>,
+                                cover: usize) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let treasury = sqlx::query!(
+            r#"SELECT is_treasury($1) AS treasury"#, seller_id
+        ).fetch_one(&mut tx).await?.treasury;
+        if treasury {
+            return Ok(false)
+        }
+        let mut media_ids = Vec::new();
+        for media in medias {
+            let sql_res = sqlx::query_as!(Media, r#"
+            INSERT INTO medias (path, thumbnail_path, media_type) VALUES ($1, $2, $3) RETURNING *
+            "#, media.path, media.thumbnail_path, media.media_type)
+                .fetch_one(&mut tx)
+                .await?;
+            media_ids.push(sql_res.id);
+        }
         let sql_res2 = sqlx::query_as!(Product, r#"
-        INSERT INTO products(product_type, seller_id, description, price, media_id) VALUES ($1, $2, $3, $4, $5) RETURNING *
-        "#, product_type, seller_id, description, price, sql_res.id).fetch_one(&self.pool).await.unwrap();
+        INSERT INTO products(product_type, seller_id, description, price, stock, media_id) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *
+        "#, product_type, seller_id, description, price, stock, media_ids[cover]).fetch_one(&mut tx).await?;
+        for (position, media_id) in media_ids.iter().enumerate() {
+            sqlx::query!(
+                r#"INSERT INTO product_medias (product_id, media_id, position) VALUES ($1, $2, $3)"#,
+                sql_res2.id, *media_id, position as i32
+            ).execute(&mut tx).await?;
+        }
+        tx.commit().await?;
         Ok(true)
     }
     /*
@@ -242,131 +490,1848 @@ impl Database {
         sql_res
     }
 
-    pub async fn db_get_products_feed(&self, id: i64) -> Result<Vec<Feed>, sqlx::Error> {
-        let sql_res = sqlx::query_as!(Feed,
+    // only the seller can edit a product, missing fields are left unchanged
+    pub async fn db_edit_product(&self, id: i64, product_id: i64, input: EditProductInput) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE products SET description = COALESCE($1, description), price = COALESCE($2, price)
+                WHERE id = $3 AND seller_id = $4 AND deleted_at IS NULL
+            "#,
+            input.description, input.price.map(|price| price.0), product_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    /// Remove a product from sale.
+    /// A sold product is only marked as deleted so its buyers keep access to it,
+    /// a product never sold is deleted along with its medias, which are returned so their files can be removed.
+    /// None if the user doesn't sell the product.
+    pub async fn db_delete_product(&self, id: i64, product_id: i64) -> Result<Option<Vec<Media>>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Product,
+            r#"UPDATE products SET deleted_at = NOW() WHERE id = $1 AND seller_id = $2 AND deleted_at IS NULL RETURNING *"#,
+            product_id, id
+        ).fetch_one(&mut tx).await;
+        let product = match Database::_handle_optional_result(sql_res)? {
+            Some(product) => product,
+            None => return Ok(None),
+        };
+        // an open auction ends unsold, its highest bidder gets their held quadreum back
+        let sql_res = sqlx::query!(
+            r#"UPDATE auctions SET status = 'UNSOLD' WHERE product_id = $1 AND status = 'OPEN' RETURNING id"#, product.id
+        ).fetch_one(&mut tx).await;
+        if let Some(auction) = Database::_handle_optional_result(sql_res)? {
+            let sql_res = sqlx::query!(
+                r#"SELECT bidder_id, amount FROM bids WHERE auction_id = $1 ORDER BY amount DESC LIMIT 1"#, auction.id
+            ).fetch_one(&mut tx).await;
+            if let Some(highest) = Database::_handle_optional_result(sql_res)? {
+                Database::_release_bid(&mut tx, highest.bidder_id, highest.amount, product.id).await?;
+            }
+        }
+        // orders keep their product even once refunded and their purchase removed
+        let sold = sqlx::query!(
+            r#"
+                SELECT EXISTS(SELECT 1 FROM purchases WHERE product_id = $1)
+                OR EXISTS(SELECT 1 FROM orders WHERE product_id = $1) AS sold
+            "#,
+            product.id
+        ).fetch_one(&mut tx).await?.sold;
+        let mut medias = Vec::new();
+        if !sold {
+            medias = sqlx::query_as!(Media,
+                r#"
+                    WITH gallery AS (DELETE FROM product_medias WHERE product_id = $1 RETURNING media_id),
+                    product AS (DELETE FROM products WHERE id = $1 RETURNING media_id)
+                    DELETE FROM medias WHERE id IN (SELECT media_id FROM gallery UNION SELECT media_id FROM product)
+                    RETURNING *
+                "#, product.id
+            ).fetch_all(&mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(Some(medias))
+    }
+
+    pub async fn db_get_product_splits(&self, product_id: i64) -> Result<Vec<Split>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Split,
+            r#"SELECT user_id, bps, kind FROM product_splits WHERE product_id = $1 ORDER BY bps DESC"#, product_id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // only the seller defines the splits, the previous ones are replaced
+    pub async fn db_set_product_splits(&self, id: i64, product_id: i64, input: SplitsInput) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Product,
+            r#"SELECT * FROM products WHERE id = $1 AND seller_id = $2 AND deleted_at IS NULL FOR UPDATE"#,
+            product_id, id
+        ).fetch_one(&mut tx).await;
+        if Database::_handle_optional_result(sql_res)?.is_none() {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"DELETE FROM product_splits WHERE product_id = $1"#, product_id
+        ).execute(&mut tx).await?;
+        for split in input.splits {
+            let treasury = sqlx::query!(
+                r#"SELECT is_treasury($1) AS treasury"#, split.user_id
+            ).fetch_one(&mut tx).await?.treasury;
+            if treasury {
+                return Ok(false)
+            }
+            sqlx::query!(
+                r#"INSERT INTO product_splits (product_id, user_id, bps, kind) VALUES ($1, $2, $3, $4)"#,
+                product_id, split.user_id, split.bps, split.kind
+            ).execute(&mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn db_get_products_feed(&self, id: i64, sort: String) -> Result<Vec<Feed>, sqlx::Error> {
+        let mut sql_res = sqlx::query_as!(Feed,
         r#"
             SELECT products.id, products.seller_id, users.username, users.avatar, products.product_type, products.description,
-            products.price, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at
+            products.price, active_sales.price AS sale_price, products.stock, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at,
+            COALESCE(product_galleries.medias, '[]') AS medias,
+            EXISTS(SELECT 1 FROM product_likes WHERE product_likes.product_id = products.id AND product_likes.user_id = $2) AS liked_by_me,
+            (SELECT COUNT(*) FROM comments WHERE comments.product_id = products.id) AS comment_count,
+            is_entitled($2, products.id) AS entitled
             FROM products INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
-            WHERE products.buyers_id = 0 ORDER BY products.created_at DESC
-        "#).fetch_all(&self.pool).await?;
+            LEFT JOIN product_galleries ON product_galleries.product_id = products.id
+            LEFT JOIN active_sales ON active_sales.product_id = products.id
+            LEFT JOIN product_scores ON product_scores.product_id = products.id
+            WHERE (products.stock IS NULL OR products.stock > 0) AND products.deleted_at IS NULL
+            AND NOT is_blocked($2, products.seller_id)
+            ORDER BY CASE
+                WHEN $1 = 'trending' THEN COALESCE(product_scores.trending, 0)
+                WHEN $1 = 'top' THEN COALESCE(product_scores.top, 0)
+            END DESC NULLS LAST, products.created_at DESC
+        "#, sort, id).fetch_all(&self.pool).await?;
+        sql_res.iter_mut().for_each(Feed::hide_locked_media);
         Ok(sql_res)
     }
 
+    pub async fn db_is_product_listed(&self, product_id: i64) -> Result<bool, sqlx::Error> {
+        let listed = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM products WHERE id = $1 AND deleted_at IS NULL) AS listed"#, product_id
+        ).fetch_one(&self.pool).await?.listed;
+        Ok(listed)
+    }
+
+    pub async fn db_add_product_views(&self, product_id: i64, views: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE products SET views = views + $1 WHERE id = $2"#, views, product_id
+        ).execute(&self.pool).await?;
+        Ok(true)
+    }
+
+    pub async fn db_refresh_product_scores(&self) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"REFRESH MATERIALIZED VIEW CONCURRENTLY product_scores"#
+        ).execute(&self.pool).await?;
+        Ok(true)
+    }
+
     pub async fn db_get_my_products_feed(&self, id: i64) -> Result<Vec<Feed>, sqlx::Error> {
+        self.db_get_products_feed_by_user(id, id).await
+    }
+
+    // id is the user looking at the feed, user_id the seller
+    pub async fn db_get_products_feed_by_user(&self, id: i64, user_id: i64) -> Result<Vec<Feed>, sqlx::Error> {
+        let mut sql_res = sqlx::query_as!(Feed,
+        r#"
+            SELECT products.id, products.seller_id, users.username, users.avatar, products.product_type, products.description,
+            products.price, active_sales.price AS sale_price, products.stock, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at,
+            COALESCE(product_galleries.medias, '[]') AS medias,
+            EXISTS(SELECT 1 FROM product_likes WHERE product_likes.product_id = products.id AND product_likes.user_id = $2) AS liked_by_me,
+            (SELECT COUNT(*) FROM comments WHERE comments.product_id = products.id) AS comment_count,
+            is_entitled($2, products.id) AS entitled
+            FROM products INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
+            LEFT JOIN product_galleries ON product_galleries.product_id = products.id
+            LEFT JOIN active_sales ON active_sales.product_id = products.id
+            WHERE users.id = $1 AND products.deleted_at IS NULL AND NOT is_blocked($2, $1)
+            ORDER BY products.created_at DESC
+        "#, user_id, id).fetch_all(&self.pool).await?;
+        sql_res.iter_mut().for_each(Feed::hide_locked_media);
+        Ok(sql_res)
+    }
+
+    // a deleted product stays visible to its buyer
+    pub async fn db_get_product_detail(&self, id: i64, product_id: i64) -> Result<Option<ProductDetail>, sqlx::Error> {
         let sql_res = sqlx::query_as!(Feed,
         r#"
             SELECT products.id, products.seller_id, users.username, users.avatar, products.product_type, products.description,
-            products.price, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at
+            products.price, active_sales.price AS sale_price, products.stock, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at,
+            COALESCE(product_galleries.medias, '[]') AS medias,
+            EXISTS(SELECT 1 FROM product_likes WHERE product_likes.product_id = products.id AND product_likes.user_id = $2) AS liked_by_me,
+            (SELECT COUNT(*) FROM comments WHERE comments.product_id = products.id) AS comment_count,
+            is_entitled($2, products.id) AS entitled
+            FROM products INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
+            LEFT JOIN product_galleries ON product_galleries.product_id = products.id
+            LEFT JOIN active_sales ON active_sales.product_id = products.id
+            WHERE products.id = $1 AND (products.deleted_at IS NULL OR
+            EXISTS(SELECT 1 FROM purchases WHERE purchases.product_id = products.id AND purchases.buyer_id = $2))
+            AND NOT is_blocked($2, products.seller_id)
+        "#, product_id, id).fetch_one(&self.pool).await;
+        let mut product = match Database::_handle_optional_result(sql_res)? {
+            Some(product) => product,
+            None => return Ok(None),
+        };
+        product.hide_locked_media();
+
+        let purchased_by_me = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM purchases WHERE product_id = $1 AND buyer_id = $2) AS purchased"#,
+            product_id, id
+        ).fetch_one(&self.pool).await?.purchased;
+
+        let mut related = sqlx::query_as!(Feed,
+        r#"
+            SELECT products.id, products.seller_id, users.username, users.avatar, products.product_type, products.description,
+            products.price, active_sales.price AS sale_price, products.stock, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at,
+            COALESCE(product_galleries.medias, '[]') AS medias,
+            EXISTS(SELECT 1 FROM product_likes WHERE product_likes.product_id = products.id AND product_likes.user_id = $3) AS liked_by_me,
+            (SELECT COUNT(*) FROM comments WHERE comments.product_id = products.id) AS comment_count,
+            is_entitled($3, products.id) AS entitled
             FROM products INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
-            WHERE users.id = $1 ORDER BY products.created_at DESC
+            LEFT JOIN product_galleries ON product_galleries.product_id = products.id
+            LEFT JOIN active_sales ON active_sales.product_id = products.id
+            WHERE products.seller_id = $1 AND products.id != $2 AND (products.stock IS NULL OR products.stock > 0)
+            AND products.deleted_at IS NULL
+            ORDER BY products.created_at DESC LIMIT 6
+        "#, product.seller_id, product_id, id).fetch_all(&self.pool).await?;
+        related.iter_mut().for_each(Feed::hide_locked_media);
+
+        Ok(Some(ProductDetail { product, purchased_by_me, related }))
+    }
+
+    pub async fn db_get_liked_products(&self, id: i64) -> Result<Vec<Feed>, sqlx::Error> {
+        let mut sql_res = sqlx::query_as!(Feed,
+        r#"
+            SELECT products.id, products.seller_id, users.username, users.avatar, products.product_type, products.description,
+            products.price, active_sales.price AS sale_price, products.stock, products.views, products.likes, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at,
+            COALESCE(product_galleries.medias, '[]') AS medias,
+            TRUE AS liked_by_me,
+            (SELECT COUNT(*) FROM comments WHERE comments.product_id = products.id) AS comment_count,
+            is_entitled($1, products.id) AS entitled
+            FROM product_likes INNER JOIN products ON product_likes.product_id = products.id
+            INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
+            LEFT JOIN product_galleries ON product_galleries.product_id = products.id
+            LEFT JOIN active_sales ON active_sales.product_id = products.id
+            WHERE product_likes.user_id = $1 AND products.deleted_at IS NULL AND NOT is_blocked($1, products.seller_id)
+            ORDER BY product_likes.created_at DESC
         "#, id).fetch_all(&self.pool).await?;
+        sql_res.iter_mut().for_each(Feed::hide_locked_media);
         Ok(sql_res)
     }
 
-    pub async fn db_add_message(&self, user_id: i64, input: SendMessageInput) -> Result<bool, sqlx::Error> {
-        let sql_res = sqlx::query!(
-            r#"INSERT INTO messages (sender, receiver, content) VALUES($1, $2, $3)"#,
-            user_id, input.receiver, input.content
-        ).execute(&self.pool).await?;
+    // false if the product doesn't exist
+    pub async fn db_like_product(&self, id: i64, product_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let found = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM products WHERE id = $1 AND deleted_at IS NULL) AS found"#, product_id
+        ).fetch_one(&mut tx).await?.found;
+        if !found {
+            return Ok(false)
+        }
+        let inserted = sqlx::query!(
+            r#"INSERT INTO product_likes (user_id, product_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+            id, product_id
+        ).execute(&mut tx).await?;
+        // liking twice is a no-op, the counter only moves on a new like
+        if inserted == 1 {
+            sqlx::query!(
+                r#"UPDATE products SET likes = likes + 1 WHERE id = $1"#, product_id
+            ).execute(&mut tx).await?;
+        }
+        tx.commit().await?;
         Ok(true)
     }
 
+    pub async fn db_unlike_product(&self, id: i64, product_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            r#"DELETE FROM product_likes WHERE user_id = $1 AND product_id = $2"#,
+            id, product_id
+        ).execute(&mut tx).await?;
+        if deleted == 1 {
+            sqlx::query!(
+                r#"UPDATE products SET likes = likes - 1 WHERE id = $1"#, product_id
+            ).execute(&mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
 
-    pub async fn db_get_all_threads(&self, user_id: i64) -> Result<Vec<Thread>, sqlx::Error> {
-        let sql_res = sqlx::query_as!(Thread,
-            r#"
-                SELECT users.id, users.username, users.avatar, m.content, m.created_at
-                FROM messages m
-                INNER JOIN users ON users.id = CASE WHEN m.sender = $1 THEN m.receiver ELSE m.sender END
-                WHERE $1 IN (m.sender, m.receiver) AND
-                (LEAST(m.sender, m.receiver), GREATEST(m.sender, m.receiver), m.created_at) IN
-                (SELECT LEAST(m2.sender, m2.receiver), GREATEST(m2.sender, m2.receiver), MAX(m2.created_at)
-                FROM messages m2
-                GROUP BY LEAST(m2.sender, m2.receiver), GREATEST(m2.sender, m2.receiver)
-                ) ORDER BY m.created_at DESC
-            "#, user_id
-        ).fetch_all(&self.pool).await?;
-        Ok(sql_res)
+    // None if the product doesn't exist or the parent is not a comment of the product
+    pub async fn db_add_comment(&self, id: i64, product_id: i64, input: CommentInput) -> Result<Option<Comment>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Comment,
+        r#"
+            WITH comment AS (
+                INSERT INTO comments (product_id, author_id, parent_id, content)
+                SELECT $1, $2, $3, $4
+                WHERE EXISTS(SELECT 1 FROM products WHERE id = $1 AND deleted_at IS NULL)
+                AND ($3::BIGINT IS NULL OR EXISTS(SELECT 1 FROM comments WHERE id = $3 AND product_id = $1))
+                RETURNING *
+            )
+            SELECT comment.id, comment.product_id, comment.author_id, users.username, users.avatar, comment.parent_id,
+            comment.content, 0::BIGINT AS reply_count, comment.edited_at, comment.created_at
+            FROM comment INNER JOIN users ON comment.author_id = users.id
+        "#, product_id, id, input.parent_id, input.content
+        ).fetch_one(&self.pool).await;
+        Database::_handle_optional_result(sql_res)
     }
 
-    pub async fn db_get_all_messages(&self, input: AllMessagesInput) -> Result<Vec<Message>, sqlx::Error> {
-        let sql_res = sqlx::query_as!(Message,
+    pub async fn db_get_comments(&self, product_id: i64, query: CommentsQuery) -> Result<Vec<Comment>, sqlx::Error> {
+        let limit = query.limit.unwrap_or(20).min(100);
+        let sql_res = sqlx::query_as!(Comment,
         r#"
-            SELECT * FROM messages WHERE sender = $1 AND receiver = $2 OR sender = $2 AND receiver = $1 ORDER BY created_at ASC
-        "#, input.user1, input.user2
+            SELECT comments.id, comments.product_id, comments.author_id, users.username, users.avatar, comments.parent_id,
+            comments.content, (SELECT COUNT(*) FROM comments replies WHERE replies.parent_id = comments.id) AS reply_count,
+            comments.edited_at, comments.created_at
+            FROM comments INNER JOIN users ON comments.author_id = users.id
+            WHERE comments.product_id = $1 AND comments.parent_id IS NOT DISTINCT FROM $2
+            AND ($3::BIGINT IS NULL OR comments.id < $3)
+            ORDER BY comments.id DESC LIMIT $4
+        "#, product_id, query.parent_id, query.before, limit
         ).fetch_all(&self.pool).await?;
         Ok(sql_res)
     }
 
-    pub async fn db_update_profile(&self, id: i64, bio: String, path: String) -> Result<bool, sqlx::Error>{
-        let sql_res = sqlx::query!(
-            r#"UPDATE users SET bio = $1, avatar = $2 WHERE id = $3"#, bio, path, id
+    // only the author can edit a comment
+    pub async fn db_edit_comment(&self, id: i64, comment_id: i64, input: EditCommentInput) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"UPDATE comments SET content = $1, edited_at = NOW() WHERE id = $2 AND author_id = $3"#,
+            input.content, comment_id, id
         ).execute(&self.pool).await?;
-        Ok(true)
+        Ok(updated == 1)
     }
 
-    pub async fn db_buy_products(&self, id: i64, buy_products: BuyProducts) -> Result<bool, sqlx::Error> {
-        let rnd_hex_string = "0307a5a1729ac61583c3eefc23ce7fb6
-        d6995fe67e41a5e59c1030336c2067b0
-        7b9c0b09f66b8b72051bb02a8a928070
-        6f34159f40f5931b6922e1d8c8389808
-        81e74c639c1edaac8b288e4be9e51fe3
-        555d7020d503fa62e009698515220c0f
-        031a9c5f9efbbb0a57836a34f540eb2a
-        20c0d95f2a935e98df478f694d7f0d41
-        95b7a4f2b1882a4997ae596bc5cd344f
-        970656abbbe33f8dbc4fc0ce96a592bb
-        077e898a20996e84fea8f2fb2af20d61
-        d8670eafa34d5fc2b175a50ad004a3d2
-        edee9beb04d1ecc90ad4323b376230e8
-        1028294f3e10f1d92c124cc5ec0547e1
-        f2e75b34a98aef2f5e97b2fa8f8a4342
-        5bd180424362a5aea0d55c937050b617";
-
-        let mut buyer = sqlx::query_as!(
-            User,
-            r#"SELECT * FROM users where id = $1"#,
-            id
-        )
-            .fetch_one(&self.pool)
-            .await?;
+    // the author or the seller of the product can delete a comment, replies go with it
+    pub async fn db_delete_comment(&self, id: i64, comment_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM comments WHERE id = $1 AND
+                (author_id = $2 OR product_id IN (SELECT products.id FROM products WHERE products.seller_id = $2))
+            "#,
+            comment_id, id
+        ).execute(&self.pool).await?;
+        Ok(deleted == 1)
+    }
 
-        let mut products_list: Vec<Product> = Vec::new();
-        for product_id in buy_products.products {
-            products_list.push(self.db_get_product_by_id(product_id).await?)
+    // returns false if the user can't post in the thread or share the product
+    pub async fn db_add_message(&self, user_id: i64, input: SendMessageInput) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let thread_id = match Database::_message_thread(&mut tx, user_id, input.thread_id, input.receiver).await? {
+            Some(thread_id) => thread_id,
+            None => return Ok(false),
+        };
+        if let Some(product_id) = input.product_id {
+            let shared = sqlx::query!(
+                r#"SELECT EXISTS(SELECT 1 FROM products WHERE id = $1 AND deleted_at IS NULL) AS shared"#, product_id
+            ).fetch_one(&mut tx).await?.shared;
+            if !shared {
+                return Ok(false)
+            }
         }
+        sqlx::query!(
+            r#"
+                INSERT INTO messages (thread_id, sender, content, kind, product_id)
+                VALUES ($1, $2, $3, CASE WHEN $4::BIGINT IS NULL THEN 'TEXT' ELSE 'PRODUCT' END, $4)
+            "#,
+            thread_id, user_id, input.content, input.product_id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
 
-        let mut price = 0i64;
-        for product in &products_list {
-            price += product.price;
+    // the attachment is saved as a media like the ones of the products
+    pub async fn db_add_media_message(&self,
+                                      user_id: i64,
+                                      thread_id: Option<i64>,
+                                      receiver: Option<i64>,
+                                      caption: String,
+                                      media: NewMedia) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let thread_id = match Database::_message_thread(&mut tx, user_id, thread_id, receiver).await? {
+            Some(thread_id) => thread_id,
+            None => return Ok(false),
+        };
+        let media = sqlx::query_as!(Media,
+            r#"INSERT INTO medias (path, thumbnail_path, media_type) VALUES ($1, $2, $3) RETURNING *"#,
+            media.path, media.thumbnail_path, media.media_type
+        ).fetch_one(&mut tx).await?;
+        sqlx::query!(
+            r#"INSERT INTO messages (thread_id, sender, content, kind, media_id) VALUES ($1, $2, $3, 'MEDIA', $4)"#,
+            thread_id, user_id, caption, media.id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // the thread a message goes to: thread_id if the user is a participant, else the direct thread with receiver
+    // None if the receiver doesn't exist or one of the users of a direct thread blocked the other
+    // posting in a thread accepts it when it was a message request
+    async fn _message_thread(tx: &mut PgTransaction,
+                             user_id: i64,
+                             thread_id: Option<i64>,
+                             receiver: Option<i64>) -> Result<Option<i64>, sqlx::Error> {
+        let thread_id = match (thread_id, receiver) {
+            (Some(thread_id), _) => {
+                let blocked = sqlx::query!(
+                    r#"
+                        SELECT EXISTS(SELECT 1 FROM threads WHERE id = $1 AND user_low IS NOT NULL
+                        AND is_blocked(user_low, user_high)) AS blocked
+                    "#,
+                    thread_id
+                ).fetch_one(&mut *tx).await?.blocked;
+                if blocked || !Database::_is_participant(tx, thread_id, user_id).await? {
+                    return Ok(None)
+                }
+                thread_id
+            }
+            (None, Some(receiver)) if receiver != user_id => {
+                let reachable = sqlx::query!(
+                    r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1) AND NOT is_blocked($1, $2) AND NOT is_treasury($1) AS reachable"#,
+                    receiver, user_id
+                ).fetch_one(&mut *tx).await?.reachable;
+                if !reachable {
+                    return Ok(None)
+                }
+                Database::_direct_thread(tx, user_id, receiver).await?
+            }
+            _ => return Ok(None),
+        };
+        sqlx::query!(
+            r#"UPDATE thread_participant SET accepted = TRUE WHERE thread_id = $1 AND user_id = $2 AND NOT accepted"#,
+            thread_id, user_id
+        ).execute(&mut *tx).await?;
+        Ok(Some(thread_id))
+    }
+
+    // group chat of the user and the participants, returns its id
+    // the users blocked with the creator are left out, it is a message request for those who don't follow them
+    pub async fn db_create_thread(&self, id: i64, input: NewThreadInput) -> Result<Option<i64>, sqlx::Error> {
+        let mut participants = input.participants;
+        participants.retain(|participant| *participant != id);
+        if participants.is_empty() {
+            return Ok(None)
         }
-        if buyer.quadreum < price {
-            return Ok(false)
+        participants.push(id);
+        let mut tx = self.pool.begin().await?;
+        let thread = sqlx::query!(
+            r#"INSERT INTO threads (title) VALUES ($1) RETURNING id"#, input.title
+        ).fetch_one(&mut tx).await?;
+        for participant in participants {
+            sqlx::query!(
+                r#"
+                    INSERT INTO thread_participant (thread_id, user_id, accepted)
+                    SELECT $1, $2, $2 = $3 OR EXISTS(SELECT 1 FROM follows WHERE followee_id = $3 AND follower_id = $2)
+                    WHERE NOT is_blocked($2, $3) AND NOT is_treasury($2)
+                    ON CONFLICT DO NOTHING
+                "#,
+                thread.id, participant, id
+            ).execute(&mut tx).await?;
         }
-        buyer.quadreum -=  price;
-        //set buyer new quadreum amount
-        sqlx::query!(
-            r#"UPDATE users SET quadreum = $1 WHERE id = $2"#, buyer.quadreum, id
+        tx.commit().await?;
+        Ok(Some(thread.id))
+    }
+
+    // a participant of a group chat adds another user to it
+    pub async fn db_add_participant(&self, id: i64, thread_id: i64, input: AddParticipantInput) -> Result<bool, sqlx::Error> {
+        let added = sqlx::query!(
+            r#"
+                INSERT INTO thread_participant (thread_id, user_id, accepted)
+                SELECT threads.id, $3, EXISTS(SELECT 1 FROM follows WHERE followee_id = $2 AND follower_id = $3) FROM threads
+                WHERE threads.id = $1 AND threads.user_low IS NULL AND NOT is_blocked($2, $3) AND NOT is_treasury($3)
+                AND EXISTS(SELECT 1 FROM thread_participant WHERE thread_id = $1 AND user_id = $2)
+                ON CONFLICT DO NOTHING
+            "#,
+            thread_id, id, input.user_id
         ).execute(&self.pool).await?;
-        for product in &products_list {
-            //Get ethereum address of the seller
-            let mut seller = sqlx::query_as!(
-            User,
-            r#"SELECT * FROM users where id = $1"#,
-            product.seller_id
-        ).fetch_one(&self.pool).await?;
-            println!("Transaction: {} => {}\n ethereum.Quadreum: ERC777\nABI:{}, ", buyer.eth_address, seller.eth_address, rnd_hex_string);
-            // set seller new quadreum amount
-            sqlx::query!(
-            r#"UPDATE users SET quadreum = quadreum + $1 WHERE id = $2"#, product.price, product.seller_id
+        Ok(added == 1)
+    }
+
+    pub async fn db_leave_thread(&self, id: i64, thread_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM thread_participant WHERE thread_id = $1 AND user_id = $2
+                AND thread_id IN (SELECT threads.id FROM threads WHERE threads.user_low IS NULL)
+            "#,
+            thread_id, id
         ).execute(&self.pool).await?;
-            // udate buyers id in producte
-            sqlx::query!(
-            r#"UPDATE products SET buyers_id = $1 WHERE id = $2"#, id, product.id
-            ).execute(&self.pool).await?;
-        }
-        Ok(true)
+        Ok(deleted == 1)
+    }
+
+    // hides the thread for the user only, it comes back with the next message
+    pub async fn db_hide_thread(&self, id: i64, thread_id: i64) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"UPDATE thread_participant SET hidden_at = NOW() WHERE thread_id = $1 AND user_id = $2"#,
+            thread_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    pub async fn db_get_all_threads(&self, user_id: i64) -> Result<Vec<Thread>, sqlx::Error> {
+        self._get_threads(user_id, true).await
+    }
+
+    // threads started by users the user doesn't follow
+    pub async fn db_get_message_requests(&self, user_id: i64) -> Result<Vec<Thread>, sqlx::Error> {
+        self._get_threads(user_id, false).await
+    }
+
+    // accepting a request moves the thread to the list of the user
+    pub async fn db_accept_thread(&self, id: i64, thread_id: i64) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"UPDATE thread_participant SET accepted = TRUE WHERE thread_id = $1 AND user_id = $2"#,
+            thread_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    // the direct threads with a blocked user are left out
+    async fn _get_threads(&self, user_id: i64, accepted: bool) -> Result<Vec<Thread>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Thread,
+            r#"
+                SELECT threads.id, threads.title, threads.user_low IS NULL AS is_group,
+                (SELECT json_agg(json_build_object('id', users.id, 'username', users.username, 'avatar', users.avatar))
+                FROM thread_participant participants INNER JOIN users ON users.id = participants.user_id
+                WHERE participants.thread_id = threads.id) AS participants,
+                COALESCE(messages.content, '') AS content, threads.last_message_at AS created_at,
+                (SELECT COUNT(*) FROM messages unread WHERE unread.thread_id = threads.id
+                AND unread.id > thread_participant.last_read_message_id AND unread.sender != $1) AS unread_count
+                FROM thread_participant INNER JOIN threads ON threads.id = thread_participant.thread_id
+                LEFT JOIN messages ON messages.id = threads.last_message_id
+                WHERE thread_participant.user_id = $1 AND thread_participant.accepted = $2
+                AND (thread_participant.hidden_at IS NULL OR threads.last_message_at > thread_participant.hidden_at)
+                AND (threads.user_low IS NULL OR NOT is_blocked(threads.user_low, threads.user_high))
+                ORDER BY threads.last_message_at DESC
+            "#, user_id, accepted
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
     }
-}
 
+    // messages of the direct thread of the two users
+    pub async fn db_get_all_messages(&self, input: AllMessagesInput) -> Result<Vec<Message>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(MessageRow,
+        r#"
+            SELECT message_details.* FROM message_details INNER JOIN threads ON threads.id = message_details.thread_id
+            WHERE threads.user_low = LEAST($1::BIGINT, $2::BIGINT) AND threads.user_high = GREATEST($1::BIGINT, $2::BIGINT)
+            ORDER BY message_details.id ASC
+        "#, input.user1, input.user2
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res.into_iter().map(Message::from).collect())
+    }
+
+    // None if the user is not a participant of the thread
+    pub async fn db_get_thread_messages(&self, id: i64, thread_id: i64) -> Result<Option<Vec<Message>>, sqlx::Error> {
+        let participant = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM thread_participant WHERE thread_id = $1 AND user_id = $2) AS participant"#,
+            thread_id, id
+        ).fetch_one(&self.pool).await?.participant;
+        if !participant {
+            return Ok(None)
+        }
+        let sql_res = sqlx::query_as!(MessageRow,
+            r#"SELECT * FROM message_details WHERE thread_id = $1 ORDER BY id ASC"#, thread_id
+        ).fetch_all(&self.pool).await?;
+        Ok(Some(sql_res.into_iter().map(Message::from).collect()))
+    }
+
+    pub async fn db_get_message(&self, message_id: i64) -> Result<Option<Message>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(MessageRow,
+            r#"SELECT * FROM message_details WHERE id = $1"#, message_id
+        ).fetch_one(&self.pool).await;
+        Ok(Database::_handle_optional_result(sql_res)?.map(Message::from))
+    }
+
+    // the participants to notify of an event in the thread, nobody in a direct thread where one user blocked the other
+    pub async fn db_get_thread_participants(&self, thread_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+        let sql_res = sqlx::query!(
+            r#"
+                SELECT thread_participant.user_id FROM thread_participant
+                INNER JOIN threads ON threads.id = thread_participant.thread_id
+                WHERE thread_participant.thread_id = $1
+                AND (threads.user_low IS NULL OR NOT is_blocked(threads.user_low, threads.user_high))
+            "#,
+            thread_id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res.into_iter().map(|participant| participant.user_id).collect())
+    }
+
+    // returns the id of the last message read in the thread, None if the user is not a participant
+    // the messages of the others read for the first time get their read_at
+    pub async fn db_mark_thread_read(&self, id: i64, thread_id: i64, input: MarkAsReadInput) -> Result<Option<i64>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query!(
+            r#"
+                WITH previous AS (
+                    SELECT last_read_message_id FROM thread_participant WHERE thread_id = $1 AND user_id = $2 FOR UPDATE
+                )
+                UPDATE thread_participant
+                SET last_read_message_id = GREATEST(last_read_message_id, COALESCE(LEAST($3, threads.last_message_id), 0))
+                FROM threads WHERE threads.id = thread_participant.thread_id
+                AND thread_participant.thread_id = $1 AND thread_participant.user_id = $2
+                RETURNING thread_participant.last_read_message_id, (SELECT last_read_message_id FROM previous) AS previous
+            "#,
+            thread_id, id, input.message_id
+        ).fetch_one(&mut tx).await;
+        let (last_read, previous) = match Database::_handle_optional_result(sql_res)? {
+            Some(participant) => (participant.last_read_message_id, participant.previous),
+            None => return Ok(None),
+        };
+        if last_read == previous {
+            return Ok(Some(last_read))
+        }
+        sqlx::query!(
+            r#"
+                UPDATE messages SET read_at = NOW()
+                WHERE thread_id = $1 AND id > $3 AND id <= $4 AND sender != $2 AND read_at IS NULL
+            "#,
+            thread_id, id, previous, last_read
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        let mut participants = self.db_get_thread_participants(thread_id).await?;
+        participants.retain(|participant| *participant != id);
+        self.db_notify(participants, ServerEvent::Read { sender: id, thread_id, message_id: last_read }).await?;
+        Ok(Some(last_read))
+    }
+
+    // the sender can edit a message for 15 minutes
+    pub async fn db_edit_message(&self, id: i64, message_id: i64, input: EditMessageInput) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE messages SET content = $1, edited_at = NOW()
+                WHERE id = $2 AND sender = $3 AND deleted_at IS NULL AND created_at > NOW() - INTERVAL '15 minutes'
+            "#,
+            input.content, message_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    // the sender can delete a message for 15 minutes, its content and attachment are dropped
+    // the deleted attachment is returned so its files can be removed, None if the message can't be deleted
+    pub async fn db_delete_message(&self, id: i64, message_id: i64) -> Result<Option<Vec<Media>>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query!(
+            r#"
+                SELECT COALESCE(media_id, 0) AS media_id FROM messages
+                WHERE id = $1 AND sender = $2 AND deleted_at IS NULL AND created_at > NOW() - INTERVAL '15 minutes'
+                FOR UPDATE
+            "#,
+            message_id, id
+        ).fetch_one(&mut tx).await;
+        let media_id = match Database::_handle_optional_result(sql_res)? {
+            Some(message) => message.media_id,
+            None => return Ok(None),
+        };
+        sqlx::query!(
+            r#"
+                UPDATE messages SET content = '', kind = 'TEXT', media_id = NULL, product_id = NULL, deleted_at = NOW()
+                WHERE id = $1
+            "#,
+            message_id
+        ).execute(&mut tx).await?;
+        let medias = sqlx::query_as!(Media,
+            r#"DELETE FROM medias WHERE id = $1 RETURNING *"#, media_id
+        ).fetch_all(&mut tx).await?;
+        tx.commit().await?;
+        Ok(Some(medias))
+    }
+
+    pub async fn db_get_unread_count(&self, id: i64) -> Result<UnreadCount, sqlx::Error> {
+        let sql_res = sqlx::query_as!(UnreadCount,
+            r#"
+                SELECT COUNT(*) AS unread_count FROM thread_participant
+                INNER JOIN messages ON messages.thread_id = thread_participant.thread_id
+                AND messages.id > thread_participant.last_read_message_id AND messages.sender != $1
+                INNER JOIN threads ON threads.id = thread_participant.thread_id
+                WHERE thread_participant.user_id = $1 AND thread_participant.accepted
+                AND (threads.user_low IS NULL OR NOT is_blocked(threads.user_low, threads.user_high))
+            "#,
+            id
+        ).fetch_one(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // push the event to the users connected to any server instance
+    pub async fn db_notify(&self, user_ids: Vec<i64>, event: ServerEvent) -> Result<bool, sqlx::Error> {
+        if user_ids.is_empty() {
+            return Ok(false)
+        }
+        let payload = serde_json::to_string(&EventNotification::Event { user_ids, event }).unwrap();
+        // pg_notify returns void, which the query! macro can't map
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(EVENTS_CHANNEL)
+            .bind(payload)
+            .execute(&self.pool).await?;
+        Ok(true)
+    }
+
+    pub async fn db_update_profile(&self, id: i64, bio: String, path: String) -> Result<bool, sqlx::Error>{
+        let sql_res = sqlx::query!(
+            r#"UPDATE users SET bio = $1, avatar = $2 WHERE id = $3"#, bio, path, id
+        ).execute(&self.pool).await?;
+        Ok(true)
+    }
+
+    pub async fn db_buy_products(&self, id: i64, buy_products: BuyProducts) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        if !Database::_buy_products(&mut tx, id, buy_products.products, buy_products.shipping_address, buy_products.discount_code).await? {
+            return Ok(false)
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // buy the products within tx, returns false if the purchase is refused
+    // a product listed several times is bought as many times
+    async fn _buy_products(tx: &mut PgTransaction,
+                           id: i64,
+                           products: Vec<i64>,
+                           shipping_address: Option<String>,
+                           discount_code: Option<String>) -> Result<bool, sqlx::Error> {
+        // lock the buyer so concurrent purchases can't spend the same quadreum twice
+        let mut buyer = sqlx::query_as!(
+            User,
+            r#"SELECT * FROM users where id = $1 FOR UPDATE"#,
+            id
+        )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let mut products_list: Vec<Product> = Vec::new();
+        for product_id in products {
+            products_list.push(sqlx::query_as!(Product,
+            r#"SELECT * from products WHERE id = $1 FOR UPDATE"#, product_id
+            ).fetch_one(&mut *tx).await?)
+        }
+
+        if products_list.iter().any(|product| product.deleted_at.is_some()) {
+            return Ok(false)
+        }
+        // a product under auction is only sold to the winning bidder
+        for product in &products_list {
+            let auctioned = sqlx::query!(
+                r#"SELECT EXISTS(SELECT 1 FROM auctions WHERE product_id = $1 AND status = 'OPEN') AS auctioned"#,
+                product.id
+            ).fetch_one(&mut *tx).await?.auctioned;
+            if auctioned {
+                return Ok(false)
+            }
+        }
+
+        // from here product.price is the price actually paid: the running sale price if any, then the discount
+        for product in products_list.iter_mut() {
+            product.price = sqlx::query!(
+                r#"
+                    SELECT COALESCE(MIN(price), $2) AS price FROM sale_prices
+                    WHERE product_id = $1 AND starts_at <= NOW() AND ends_at > NOW()
+                "#,
+                product.id, product.price
+            ).fetch_one(&mut *tx).await?.price;
+        }
+        if let Some(code) = discount_code {
+            let sql_res = sqlx::query_as!(DiscountCode,
+                r#"SELECT * FROM discount_codes WHERE code = $1 FOR UPDATE"#, code
+            ).fetch_one(&mut *tx).await;
+            let discount = match Database::_handle_optional_result(sql_res)? {
+                Some(discount) => discount,
+                None => return Ok(false),
+            };
+            if !discount.is_usable(Utc::now()) {
+                return Ok(false)
+            }
+            let mut prices: Vec<&mut i64> = products_list.iter_mut()
+                .filter(|product| discount.applies_to(product))
+                .map(|product| &mut product.price)
+                .collect();
+            if prices.is_empty() || discount.apply(&mut prices).is_none() {
+                return Ok(false)
+            }
+            sqlx::query!(
+                r#"UPDATE discount_codes SET uses = uses + 1 WHERE id = $1"#, discount.id
+            ).execute(&mut *tx).await?;
+        }
+
+        // REAL products are limited by their stock, a MEDIA product is bought once per buyer
+        for product in &products_list {
+            let units = products_list.iter().filter(|other| other.id == product.id).count() as i64;
+            match product.stock {
+                Some(stock) if units > stock => return Ok(false),
+                None if units > 1 => return Ok(false),
+                None => {
+                    let owned = sqlx::query!(
+                        r#"SELECT EXISTS(SELECT 1 FROM purchases WHERE product_id = $1 AND buyer_id = $2) AS owned"#,
+                        product.id, id
+                    ).fetch_one(&mut *tx).await?.owned;
+                    if owned {
+                        return Ok(false)
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // physical goods need an address to be shipped to
+        let shipping_address = shipping_address.unwrap_or_default();
+        if products_list.iter().any(|product| product.product_type == "REAL") && shipping_address.trim().is_empty() {
+            return Ok(false)
+        }
+
+        let mut price = Amount::ZERO;
+        for product in &products_list {
+            price = match price.checked_add(Amount(product.price)) {
+                Some(price) => price,
+                None => return Ok(false),
+            };
+        }
+        if buyer.quadreum < price.0 {
+            return Ok(false)
+        }
+        buyer.quadreum -=  price.0;
+        //set buyer new quadreum amount
+        sqlx::query!(
+            r#"UPDATE users SET quadreum = $1 WHERE id = $2"#, buyer.quadreum, id
+        ).execute(&mut *tx).await?;
+        for product in &products_list {
+            Database::_sell_product(&mut *tx, &buyer, product, &shipping_address).await?;
+        }
+        Ok(true)
+    }
+
+    // sell one unit of the product to the buyer at product.price, the buyer is already debited
+    async fn _sell_product(tx: &mut PgTransaction,
+                           buyer: &User,
+                           product: &Product,
+                           shipping_address: &str) -> Result<bool, sqlx::Error> {
+        let rnd_hex_string = "0307a5a1729ac61583c3eefc23ce7fb6
+        d6995fe67e41a5e59c1030336c2067b0
+        7b9c0b09f66b8b72051bb02a8a928070
+        6f34159f40f5931b6922e1d8c8389808
+        81e74c639c1edaac8b288e4be9e51fe3
+        555d7020d503fa62e009698515220c0f
+        031a9c5f9efbbb0a57836a34f540eb2a
+        20c0d95f2a935e98df478f694d7f0d41
+        95b7a4f2b1882a4997ae596bc5cd344f
+        970656abbbe33f8dbc4fc0ce96a592bb
+        077e898a20996e84fea8f2fb2af20d61
+        d8670eafa34d5fc2b175a50ad004a3d2
+        edee9beb04d1ecc90ad4323b376230e8
+        1028294f3e10f1d92c124cc5ec0547e1
+        f2e75b34a98aef2f5e97b2fa8f8a4342
+        5bd180424362a5aea0d55c937050b617";
+
+        sqlx::query!(
+            r#"INSERT INTO balance_history (user_id, amount, reason, product_id) VALUES ($1, $2, 'PURCHASE', $3)"#,
+            buyer.id, -product.price, product.id
+        ).execute(&mut *tx).await?;
+        //Get ethereum address of the seller
+        let seller = sqlx::query_as!(
+            User,
+            r#"SELECT * FROM users where id = $1"#,
+            product.seller_id
+        ).fetch_one(&mut *tx).await?;
+        println!("Transaction: {} => {}\n ethereum.Quadreum: ERC777\nABI:{}, ", buyer.eth_address, seller.eth_address, rnd_hex_string);
+        let purchase = sqlx::query!(
+            r#"INSERT INTO purchases (product_id, buyer_id, price) VALUES ($1, $2, $3) RETURNING id"#,
+            product.id, buyer.id, product.price
+        ).fetch_one(&mut *tx).await?;
+        if product.product_type == "REAL" {
+            // the price is held in escrow until the buyer confirms the delivery
+            // the seller has 7 days to ship before the buyer is refunded
+            let order = sqlx::query!(
+                r#"
+                    INSERT INTO orders (product_id, buyer_id, seller_id, purchase_id, price, shipping_address, release_after)
+                    VALUES ($1, $2, $3, $4, $5, $6, NOW() + INTERVAL '7 days') RETURNING id
+                "#, product.id, buyer.id, product.seller_id, purchase.id, product.price, shipping_address
+            ).fetch_one(&mut *tx).await?;
+            sqlx::query!(
+                r#"
+                    INSERT INTO order_splits (order_id, user_id, bps, kind)
+                    SELECT $1, user_id, bps, kind FROM product_splits WHERE product_id = $2
+                "#, order.id, product.id
+            ).execute(&mut *tx).await?;
+            sqlx::query!(
+                r#"UPDATE users SET quadreum_escrow = quadreum_escrow + $1 WHERE id = $2"#, product.price, product.seller_id
+            ).execute(&mut *tx).await?;
+        } else {
+            // pay the seller, the platform and the collaborators
+            let splits = sqlx::query_as!(Split,
+                r#"SELECT user_id, bps, kind FROM product_splits WHERE product_id = $1"#, product.id
+            ).fetch_all(&mut *tx).await?;
+            Database::_credit_sale(&mut *tx, product.id, product.seller_id, product.price, splits).await?;
+        }
+        if product.stock.is_some() {
+            sqlx::query!(
+                r#"UPDATE products SET stock = stock - 1 WHERE id = $1"#, product.id
+            ).execute(&mut *tx).await?;
+        }
+        Ok(true)
+    }
+
+    pub async fn db_add_discount_code(&self, id: i64, input: DiscountCodeInput) -> Result<Option<DiscountCode>, sqlx::Error> {
+        // a code scoped to a product only works on a product of the seller
+        let sql_res = sqlx::query_as!(DiscountCode,
+            r#"
+                INSERT INTO discount_codes (seller_id, code, percent_off, amount_off, max_uses, expires_at, product_id)
+                SELECT $1, $2, $3, $4, $5, $6, $7
+                WHERE $7::BIGINT IS NULL OR EXISTS(SELECT 1 FROM products WHERE id = $7 AND seller_id = $1)
+                RETURNING *
+            "#,
+            id, input.code, input.percent_off, input.amount_off.map(|amount| amount.0), input.max_uses,
+            input.expires_at, input.product_id
+        ).fetch_one(&self.pool).await;
+        Database::_handle_optional_result(sql_res)
+    }
+
+    pub async fn db_get_my_discount_codes(&self, id: i64) -> Result<Vec<DiscountCode>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(DiscountCode,
+            r#"SELECT * FROM discount_codes WHERE seller_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    pub async fn db_delete_discount_code(&self, id: i64, discount_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"DELETE FROM discount_codes WHERE id = $1 AND seller_id = $2"#, discount_id, id
+        ).execute(&self.pool).await?;
+        Ok(deleted == 1)
+    }
+
+    // only the seller schedules a sale on a product
+    pub async fn db_add_sale_price(&self, id: i64, product_id: i64, input: SalePriceInput) -> Result<Option<SalePrice>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(SalePrice,
+            r#"
+                INSERT INTO sale_prices (product_id, price, starts_at, ends_at)
+                SELECT products.id, $3, $4, $5 FROM products
+                WHERE products.id = $1 AND products.seller_id = $2 AND products.deleted_at IS NULL
+                RETURNING *
+            "#,
+            product_id, id, input.price.0, input.starts_at, input.ends_at
+        ).fetch_one(&self.pool).await;
+        Database::_handle_optional_result(sql_res)
+    }
+
+    pub async fn db_get_sale_prices(&self, product_id: i64) -> Result<Vec<SalePrice>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(SalePrice,
+            r#"SELECT * FROM sale_prices WHERE product_id = $1 AND ends_at > NOW() ORDER BY starts_at ASC"#, product_id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    pub async fn db_delete_sale_price(&self, id: i64, sale_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM sale_prices WHERE id = $1 AND product_id IN
+                (SELECT products.id FROM products WHERE products.seller_id = $2)
+            "#,
+            sale_id, id
+        ).execute(&self.pool).await?;
+        Ok(deleted == 1)
+    }
+
+    // only the seller auctions a product, once at a time
+    pub async fn db_create_auction(&self, id: i64, product_id: i64, input: AuctionInput) -> Result<Option<Auction>, sqlx::Error> {
+        let reserve_price = input.reserve_price.unwrap_or(input.starting_price);
+        let sql_res = sqlx::query_as!(Auction,
+            r#"
+                INSERT INTO auctions (product_id, starting_price, reserve_price, ends_at, extension_secs)
+                SELECT products.id, $3, $4, $5, $6 FROM products
+                WHERE products.id = $1 AND products.seller_id = $2 AND products.deleted_at IS NULL
+                AND (products.stock IS NULL OR products.stock > 0) AND $5 > NOW()
+                ON CONFLICT (product_id) WHERE status = 'OPEN' DO NOTHING
+                RETURNING *
+            "#,
+            product_id, id, input.starting_price.0, reserve_price.0, input.ends_at, input.extension_secs.unwrap_or(300)
+        ).fetch_one(&self.pool).await;
+        Database::_handle_optional_result(sql_res)
+    }
+
+    // the running auction of the product, or its last one
+    pub async fn db_get_auction(&self, product_id: i64) -> Result<Option<AuctionDetail>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Auction,
+            r#"SELECT * FROM auctions WHERE product_id = $1 ORDER BY created_at DESC LIMIT 1"#, product_id
+        ).fetch_one(&self.pool).await;
+        let auction = match Database::_handle_optional_result(sql_res)? {
+            Some(auction) => auction,
+            None => return Ok(None),
+        };
+        let sql_res = sqlx::query_as!(Bid,
+            r#"
+                SELECT id, auction_id, bidder_id, amount, created_at FROM bids
+                WHERE auction_id = $1 ORDER BY amount DESC LIMIT 1
+            "#,
+            auction.id
+        ).fetch_one(&self.pool).await;
+        let highest_bid = Database::_handle_optional_result(sql_res)?;
+        Ok(Some(AuctionDetail { auction, highest_bid }))
+    }
+
+    pub async fn db_get_bids(&self, product_id: i64) -> Result<Vec<Bid>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Bid,
+            r#"
+                SELECT bids.id, bids.auction_id, bids.bidder_id, bids.amount, bids.created_at FROM bids
+                WHERE bids.auction_id = (SELECT id FROM auctions WHERE product_id = $1 ORDER BY created_at DESC LIMIT 1)
+                ORDER BY bids.amount DESC
+            "#,
+            product_id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // hold the amount of the bid and give back the quadreum of the outbid bidder
+    // returns false if the bid is refused
+    pub async fn db_place_bid(&self, id: i64, product_id: i64, input: BidInput) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Auction,
+            r#"SELECT * FROM auctions WHERE product_id = $1 AND status = 'OPEN' AND ends_at > NOW() FOR UPDATE"#,
+            product_id
+        ).fetch_one(&mut tx).await;
+        let auction = match Database::_handle_optional_result(sql_res)? {
+            Some(auction) => auction,
+            None => return Ok(false),
+        };
+        let product = sqlx::query_as!(Product,
+            r#"SELECT * FROM products WHERE id = $1"#, product_id
+        ).fetch_one(&mut tx).await?;
+        if product.seller_id == id {
+            return Ok(false)
+        }
+        let shipping_address = input.shipping_address.unwrap_or_default();
+        if product.product_type == "REAL" && shipping_address.trim().is_empty() {
+            return Ok(false)
+        }
+        if product.product_type == "MEDIA" {
+            let owned = sqlx::query!(
+                r#"SELECT EXISTS(SELECT 1 FROM purchases WHERE product_id = $1 AND buyer_id = $2) AS owned"#,
+                product_id, id
+            ).fetch_one(&mut tx).await?.owned;
+            if owned {
+                return Ok(false)
+            }
+        }
+
+        let sql_res = sqlx::query_as!(Bid,
+            r#"
+                SELECT id, auction_id, bidder_id, amount, created_at FROM bids
+                WHERE auction_id = $1 ORDER BY amount DESC LIMIT 1
+            "#,
+            auction.id
+        ).fetch_one(&mut tx).await;
+        match Database::_handle_optional_result(sql_res)? {
+            Some(highest) => {
+                if input.amount.0 <= highest.amount {
+                    return Ok(false)
+                }
+                Database::_release_bid(&mut tx, highest.bidder_id, highest.amount, product_id).await?;
+            }
+            None => {
+                if input.amount.0 < auction.starting_price {
+                    return Ok(false)
+                }
+            }
+        }
+
+        let held = sqlx::query!(
+            r#"
+                UPDATE users SET quadreum = quadreum - $1, quadreum_held = quadreum_held + $1
+                WHERE id = $2 AND quadreum >= $1
+            "#,
+            input.amount.0, id
+        ).execute(&mut tx).await?;
+        if held != 1 {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"INSERT INTO balance_history (user_id, amount, reason, product_id) VALUES ($1, $2, 'BID_HOLD', $3)"#,
+            id, -input.amount.0, product_id
+        ).execute(&mut tx).await?;
+        sqlx::query!(
+            r#"INSERT INTO bids (auction_id, bidder_id, amount, shipping_address) VALUES ($1, $2, $3, $4)"#,
+            auction.id, id, input.amount.0, shipping_address
+        ).execute(&mut tx).await?;
+        // a late bid gives the other bidders the time to answer
+        sqlx::query!(
+            r#"
+                UPDATE auctions SET ends_at = NOW() + extension_secs * INTERVAL '1 second'
+                WHERE id = $1 AND ends_at < NOW() + extension_secs * INTERVAL '1 second'
+            "#,
+            auction.id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn db_get_due_auctions(&self) -> Result<Vec<Auction>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Auction,
+            r#"SELECT * FROM auctions WHERE status = 'OPEN' AND ends_at <= NOW()"#
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // close the auction: the highest bidder buys the product at the bid if it reaches the reserve price
+    pub async fn db_settle_auction(&self, auction_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Auction,
+            r#"SELECT * FROM auctions WHERE id = $1 AND status = 'OPEN' AND ends_at <= NOW() FOR UPDATE"#,
+            auction_id
+        ).fetch_one(&mut tx).await;
+        let auction = match Database::_handle_optional_result(sql_res)? {
+            Some(auction) => auction,
+            None => return Ok(false),
+        };
+        let mut product = sqlx::query_as!(Product,
+            r#"SELECT * FROM products WHERE id = $1 FOR UPDATE"#, auction.product_id
+        ).fetch_one(&mut tx).await?;
+        let sql_res = sqlx::query!(
+            r#"
+                SELECT bidder_id, amount, shipping_address FROM bids
+                WHERE auction_id = $1 ORDER BY amount DESC LIMIT 1
+            "#,
+            auction.id
+        ).fetch_one(&mut tx).await;
+        let highest = Database::_handle_optional_result(sql_res)?;
+
+        let mut status = "UNSOLD";
+        if let Some(highest) = highest {
+            // the held quadreum goes back to the bidder, then pays the product like a purchase
+            Database::_release_bid(&mut tx, highest.bidder_id, highest.amount, product.id).await?;
+            let available = product.deleted_at.is_none() && product.stock.map_or(true, |stock| stock > 0);
+            if available && highest.amount >= auction.reserve_price {
+                let buyer = sqlx::query_as!(User,
+                    r#"UPDATE users SET quadreum = quadreum - $1 WHERE id = $2 RETURNING *"#,
+                    highest.amount, highest.bidder_id
+                ).fetch_one(&mut tx).await?;
+                product.price = highest.amount;
+                let shipping_address = highest.shipping_address.unwrap_or_default();
+                Database::_sell_product(&mut tx, &buyer, &product, &shipping_address).await?;
+                status = "SOLD";
+            }
+        }
+        sqlx::query!(
+            r#"UPDATE auctions SET status = $1 WHERE id = $2"#, status, auction.id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // the buyer proposes a price for the product, returns None if the offer is refused
+    pub async fn db_make_offer(&self, id: i64, product_id: i64, input: OfferInput) -> Result<Option<Offer>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Product,
+            r#"SELECT * FROM products WHERE id = $1"#, product_id
+        ).fetch_one(&mut tx).await;
+        let product = match Database::_handle_optional_result(sql_res)? {
+            Some(product) => product,
+            None => return Ok(None),
+        };
+        if !Database::_is_available(&mut tx, id, &product).await? {
+            return Ok(None)
+        }
+        let shipping_address = input.shipping_address.filter(|address| !address.trim().is_empty());
+        if product.product_type == "REAL" && shipping_address.is_none() {
+            return Ok(None)
+        }
+        if Database::_is_blocked(&mut tx, id, product.seller_id).await? {
+            return Ok(None)
+        }
+        let offer = sqlx::query_as!(Offer,
+            r#"
+                INSERT INTO offers (product_id, buyer_id, seller_id, proposed_by, price, shipping_address)
+                VALUES ($1, $2, $3, $2, $4, $5) RETURNING *
+            "#,
+            product_id, id, product.seller_id, input.price.0, shipping_address
+        ).fetch_one(&mut tx).await?;
+        let content = format!("Offer: {} quadreum for product #{}", input.price, product_id);
+        Database::_add_message(&mut tx, id, product.seller_id, &content).await?;
+        tx.commit().await?;
+        Ok(Some(offer))
+    }
+
+    pub async fn db_get_my_offers(&self, id: i64) -> Result<Vec<Offer>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Offer,
+            r#"SELECT * FROM offers WHERE buyer_id = $1 OR seller_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // a running offer waiting for the answer of the user
+    async fn _get_pending_offer(tx: &mut PgTransaction, id: i64, offer_id: i64) -> Result<Option<Offer>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Offer,
+            r#"SELECT * FROM offers WHERE id = $1 AND status = 'PENDING' AND expires_at > NOW() FOR UPDATE"#,
+            offer_id
+        ).fetch_one(&mut *tx).await;
+        match Database::_handle_optional_result(sql_res)? {
+            Some(offer) if offer.recipient() == id => Ok(Some(offer)),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn db_decline_offer(&self, id: i64, offer_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let offer = match Database::_get_pending_offer(&mut tx, id, offer_id).await? {
+            Some(offer) => offer,
+            None => return Ok(false),
+        };
+        sqlx::query!(
+            r#"UPDATE offers SET status = 'DECLINED', updated_at = NOW() WHERE id = $1"#, offer.id
+        ).execute(&mut tx).await?;
+        let content = format!("Offer declined: {} quadreum for product #{}", Amount(offer.price), offer.product_id);
+        Database::_add_message(&mut tx, id, offer.proposed_by, &content).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // replace the offer by a new one proposed by the user
+    pub async fn db_counter_offer(&self, id: i64, offer_id: i64, input: CounterOfferInput) -> Result<Option<Offer>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let offer = match Database::_get_pending_offer(&mut tx, id, offer_id).await? {
+            Some(offer) => offer,
+            None => return Ok(None),
+        };
+        if Database::_is_blocked(&mut tx, offer.buyer_id, offer.seller_id).await? {
+            return Ok(None)
+        }
+        sqlx::query!(
+            r#"UPDATE offers SET status = 'COUNTERED', updated_at = NOW() WHERE id = $1"#, offer.id
+        ).execute(&mut tx).await?;
+        let counter = sqlx::query_as!(Offer,
+            r#"
+                INSERT INTO offers (product_id, buyer_id, seller_id, proposed_by, price, parent_id, shipping_address)
+                VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *
+            "#,
+            offer.product_id, offer.buyer_id, offer.seller_id, id, input.price.0, offer.id, offer.shipping_address
+        ).fetch_one(&mut tx).await?;
+        let content = format!("Counter-offer: {} quadreum for product #{}", input.price, offer.product_id);
+        Database::_add_message(&mut tx, id, offer.proposed_by, &content).await?;
+        tx.commit().await?;
+        Ok(Some(counter))
+    }
+
+    // sell the product to the buyer at the price of the offer, returns false if the sale is refused
+    pub async fn db_accept_offer(&self, id: i64, offer_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let offer = match Database::_get_pending_offer(&mut tx, id, offer_id).await? {
+            Some(offer) => offer,
+            None => return Ok(false),
+        };
+        if Database::_is_blocked(&mut tx, offer.buyer_id, offer.seller_id).await? {
+            return Ok(false)
+        }
+        let buyer = sqlx::query_as!(User,
+            r#"SELECT * FROM users WHERE id = $1 FOR UPDATE"#, offer.buyer_id
+        ).fetch_one(&mut tx).await?;
+        let mut product = sqlx::query_as!(Product,
+            r#"SELECT * FROM products WHERE id = $1 FOR UPDATE"#, offer.product_id
+        ).fetch_one(&mut tx).await?;
+        if !Database::_is_available(&mut tx, buyer.id, &product).await? || buyer.quadreum < offer.price {
+            return Ok(false)
+        }
+        sqlx::query!(
+            r#"UPDATE users SET quadreum = quadreum - $1 WHERE id = $2"#, offer.price, buyer.id
+        ).execute(&mut tx).await?;
+        product.price = offer.price;
+        let shipping_address = offer.shipping_address.clone().unwrap_or_default();
+        Database::_sell_product(&mut tx, &buyer, &product, &shipping_address).await?;
+        sqlx::query!(
+            r#"UPDATE offers SET status = 'ACCEPTED', updated_at = NOW() WHERE id = $1"#, offer.id
+        ).execute(&mut tx).await?;
+        let content = format!("Offer accepted: {} quadreum for product #{}", Amount(offer.price), offer.product_id);
+        Database::_add_message(&mut tx, id, offer.proposed_by, &content).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // expire the offers left unanswered and tell their recipients
+    pub async fn db_expire_offers(&self) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let expired = sqlx::query_as!(Offer,
+            r#"
+                UPDATE offers SET status = 'EXPIRED', updated_at = NOW()
+                WHERE status = 'PENDING' AND expires_at <= NOW() RETURNING *
+            "#
+        ).fetch_all(&mut tx).await?;
+        for offer in &expired {
+            let content = format!("Offer expired: {} quadreum for product #{}", Amount(offer.price), offer.product_id);
+            Database::_add_message(&mut tx, offer.proposed_by, offer.recipient(), &content).await?;
+        }
+        tx.commit().await?;
+        Ok(expired.len() as u64)
+    }
+
+    pub async fn db_set_subscription_tier(&self, id: i64, input: SubscriptionTierInput) -> Result<SubscriptionTier, sqlx::Error> {
+        let sql_res = sqlx::query_as!(SubscriptionTier,
+            r#"
+                INSERT INTO subscription_tiers (creator_id, price) VALUES ($1, $2)
+                ON CONFLICT (creator_id) DO UPDATE SET price = EXCLUDED.price, updated_at = NOW()
+                RETURNING *
+            "#,
+            id, input.price.0
+        ).fetch_one(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    pub async fn db_get_subscription_tier(&self, user_id: i64) -> Result<Option<SubscriptionTier>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(SubscriptionTier,
+            r#"SELECT * FROM subscription_tiers WHERE creator_id = $1"#, user_id
+        ).fetch_one(&self.pool).await;
+        Database::_handle_optional_result(sql_res)
+    }
+
+    // running subscriptions end with their current period
+    pub async fn db_delete_subscription_tier(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"DELETE FROM subscription_tiers WHERE creator_id = $1"#, id
+        ).execute(&self.pool).await?;
+        Ok(deleted == 1)
+    }
+
+    // pay the first month, or resume a cancelled subscription still running
+    // returns false if the subscription is refused
+    pub async fn db_subscribe(&self, id: i64, creator_id: i64) -> Result<bool, sqlx::Error> {
+        if id == creator_id {
+            return Ok(false)
+        }
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(SubscriptionTier,
+            r#"SELECT * FROM subscription_tiers WHERE creator_id = $1"#, creator_id
+        ).fetch_one(&mut tx).await;
+        let tier = match Database::_handle_optional_result(sql_res)? {
+            Some(tier) => tier,
+            None => return Ok(false),
+        };
+        let sql_res = sqlx::query_as!(Subscription,
+            r#"SELECT * FROM subscriptions WHERE subscriber_id = $1 AND creator_id = $2 FOR UPDATE"#,
+            id, creator_id
+        ).fetch_one(&mut tx).await;
+        match Database::_handle_optional_result(sql_res)? {
+            Some(subscription) if subscription.status == "ACTIVE" || subscription.status == "PAST_DUE" => {
+                return Ok(false)
+            }
+            Some(subscription) if subscription.status == "CANCELLED" && subscription.current_period_end > Utc::now() => {
+                sqlx::query!(
+                    r#"UPDATE subscriptions SET status = 'ACTIVE' WHERE subscriber_id = $1 AND creator_id = $2"#,
+                    id, creator_id
+                ).execute(&mut tx).await?;
+            }
+            _ => {
+                if !Database::_pay_subscription(&mut tx, id, creator_id, tier.price).await? {
+                    return Ok(false)
+                }
+                sqlx::query!(
+                    r#"
+                        INSERT INTO subscriptions (subscriber_id, creator_id, price, current_period_end)
+                        VALUES ($1, $2, $3, NOW() + INTERVAL '1 month')
+                        ON CONFLICT (subscriber_id, creator_id) DO UPDATE SET price = EXCLUDED.price, status = 'ACTIVE',
+                        current_period_end = EXCLUDED.current_period_end, grace_until = NULL
+                    "#,
+                    id, creator_id, tier.price
+                ).execute(&mut tx).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // the subscription stops renewing, the access lasts until the end of the paid period
+    pub async fn db_unsubscribe(&self, id: i64, creator_id: i64) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE subscriptions SET status = 'CANCELLED', grace_until = NULL
+                WHERE subscriber_id = $1 AND creator_id = $2 AND status IN ('ACTIVE', 'PAST_DUE')
+            "#,
+            id, creator_id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    pub async fn db_get_my_subscriptions(&self, id: i64) -> Result<Vec<Subscription>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Subscription,
+            r#"SELECT * FROM subscriptions WHERE subscriber_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    pub async fn db_get_due_subscriptions(&self) -> Result<Vec<Subscription>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Subscription,
+            r#"SELECT * FROM subscriptions WHERE status IN ('ACTIVE', 'PAST_DUE') AND current_period_end <= NOW()"#
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    /// Debit the next month of a subscription at the current price of the tier.
+    /// A failed renewal gives a 3 days grace period, retried until it expires.
+    pub async fn db_renew_subscription(&self, subscriber_id: i64, creator_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Subscription,
+            r#"
+                SELECT * FROM subscriptions WHERE subscriber_id = $1 AND creator_id = $2
+                AND status IN ('ACTIVE', 'PAST_DUE') AND current_period_end <= NOW() FOR UPDATE
+            "#,
+            subscriber_id, creator_id
+        ).fetch_one(&mut tx).await;
+        let subscription = match Database::_handle_optional_result(sql_res)? {
+            Some(subscription) => subscription,
+            None => return Ok(false),
+        };
+        let sql_res = sqlx::query_as!(SubscriptionTier,
+            r#"SELECT * FROM subscription_tiers WHERE creator_id = $1"#, creator_id
+        ).fetch_one(&mut tx).await;
+        let tier = Database::_handle_optional_result(sql_res)?;
+
+        let renewed = match tier {
+            Some(tier) if Database::_pay_subscription(&mut tx, subscriber_id, creator_id, tier.price).await? => {
+                // a renewal late after a downtime or a grace period starts a new period now
+                sqlx::query!(
+                    r#"
+                        UPDATE subscriptions SET price = $3, status = 'ACTIVE', grace_until = NULL,
+                        current_period_end = GREATEST(current_period_end, NOW()) + INTERVAL '1 month'
+                        WHERE subscriber_id = $1 AND creator_id = $2
+                    "#,
+                    subscriber_id, creator_id, tier.price
+                ).execute(&mut tx).await?;
+                true
+            }
+            Some(_) if subscription.status == "ACTIVE" => {
+                sqlx::query!(
+                    r#"
+                        UPDATE subscriptions SET status = 'PAST_DUE', grace_until = current_period_end + INTERVAL '3 days'
+                        WHERE subscriber_id = $1 AND creator_id = $2
+                    "#,
+                    subscriber_id, creator_id
+                ).execute(&mut tx).await?;
+                false
+            }
+            Some(_) if subscription.grace_until.map_or(false, |grace_until| grace_until > Utc::now()) => false,
+            _ => {
+                sqlx::query!(
+                    r#"UPDATE subscriptions SET status = 'EXPIRED', grace_until = NULL WHERE subscriber_id = $1 AND creator_id = $2"#,
+                    subscriber_id, creator_id
+                ).execute(&mut tx).await?;
+                false
+            }
+        };
+        tx.commit().await?;
+        Ok(renewed)
+    }
+
+    // every media of the product, None if the user is not entitled to them
+    pub async fn db_get_product_medias(&self, id: i64, product_id: i64) -> Result<Option<Vec<Media>>, sqlx::Error> {
+        let entitled = sqlx::query!(
+            r#"SELECT is_entitled($1, $2) AS entitled"#, id, product_id
+        ).fetch_one(&self.pool).await?.entitled;
+        if !entitled {
+            return Ok(None)
+        }
+        let sql_res = sqlx::query_as!(Media,
+            r#"
+                SELECT medias.id, medias.path, medias.thumbnail_path, medias.media_type, medias.created_at
+                FROM product_medias INNER JOIN medias ON product_medias.media_id = medias.id
+                WHERE product_medias.product_id = $1 ORDER BY product_medias.position
+            "#,
+            product_id
+        ).fetch_all(&self.pool).await?;
+        Ok(Some(sql_res))
+    }
+
+    // a file is locked when it is the content of a product the user is not entitled to
+    // thumbnails, avatars and message attachments are not locked
+    pub async fn db_can_access_file(&self, id: Option<i64>, path: String) -> Result<bool, sqlx::Error> {
+        let locked = sqlx::query!(
+            r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM medias INNER JOIN products ON products.media_id = medias.id
+                    OR products.id IN (SELECT product_id FROM product_medias WHERE product_medias.media_id = medias.id)
+                    WHERE medias.path = $2 AND NOT COALESCE(is_entitled($1, products.id), FALSE)
+                ) AS locked
+            "#,
+            id, path
+        ).fetch_one(&self.pool).await?.locked;
+        Ok(!locked)
+    }
+
+    pub async fn db_tip_user(&self, id: i64, user_id: i64, input: TipInput) -> Result<Option<Tip>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let exists = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1) AND NOT is_treasury($1) AS exists"#, user_id
+        ).fetch_one(&mut tx).await?.exists;
+        if !exists {
+            return Ok(None)
+        }
+        let tip = match Database::_tip(&mut tx, id, user_id, None, input).await? {
+            Some(tip) => tip,
+            None => return Ok(None),
+        };
+        tx.commit().await?;
+        Ok(Some(tip))
+    }
+
+    // the tip goes to the seller of the product
+    pub async fn db_tip_product(&self, id: i64, product_id: i64, input: TipInput) -> Result<Option<Tip>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query!(
+            r#"SELECT seller_id FROM products WHERE id = $1 AND deleted_at IS NULL"#, product_id
+        ).fetch_one(&mut tx).await;
+        let seller_id = match Database::_handle_optional_result(sql_res)? {
+            Some(product) => product.seller_id,
+            None => return Ok(None),
+        };
+        let tip = match Database::_tip(&mut tx, id, seller_id, Some(product_id), input).await? {
+            Some(tip) => tip,
+            None => return Ok(None),
+        };
+        tx.commit().await?;
+        Ok(Some(tip))
+    }
+
+    // move the tip from the sender to the recipient, returns None if the sender can't pay it
+    async fn _tip(tx: &mut PgTransaction, id: i64, recipient_id: i64, product_id: Option<i64>, input: TipInput) -> Result<Option<Tip>, sqlx::Error> {
+        if id == recipient_id || Database::_is_blocked(tx, id, recipient_id).await? {
+            return Ok(None)
+        }
+        let debited = sqlx::query!(
+            r#"UPDATE users SET quadreum = quadreum - $1 WHERE id = $2 AND quadreum >= $1"#, input.amount.0, id
+        ).execute(&mut *tx).await?;
+        if debited != 1 {
+            return Ok(None)
+        }
+        sqlx::query!(
+            r#"INSERT INTO balance_history (user_id, amount, reason, product_id) VALUES ($1, $2, 'TIP', $3)"#,
+            id, -input.amount.0, product_id
+        ).execute(&mut *tx).await?;
+        Database::_credit(tx, recipient_id, input.amount.0, "TIP", product_id).await?;
+
+        let message = input.message.filter(|message| !message.trim().is_empty());
+        let tip = sqlx::query_as!(Tip,
+            r#"
+                INSERT INTO tips (sender_id, recipient_id, product_id, amount, message)
+                VALUES ($1, $2, $3, $4, $5) RETURNING *
+            "#,
+            id, recipient_id, product_id, input.amount.0, message
+        ).fetch_one(&mut *tx).await?;
+        if input.post_in_thread.unwrap_or(false) {
+            let mut content = match product_id {
+                Some(product_id) => format!("Tip: {} quadreum for product #{}", input.amount, product_id),
+                None => format!("Tip: {} quadreum", input.amount),
+            };
+            if let Some(message) = &tip.message {
+                content = format!("{}\n{}", content, message);
+            }
+            Database::_add_message(tx, id, recipient_id, &content).await?;
+        }
+        Ok(Some(tip))
+    }
+
+    pub async fn db_get_received_tips(&self, id: i64) -> Result<Vec<Tip>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Tip,
+            r#"SELECT * FROM tips WHERE recipient_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // MEDIA products are added once, REAL products up to their stock
+    pub async fn db_add_to_cart(&self, id: i64, input: AddToCartInput) -> Result<bool, sqlx::Error> {
+        let quantity = input.quantity.unwrap_or(1).max(1);
+        let added = sqlx::query!(
+            r#"
+                INSERT INTO cart_items (user_id, product_id, quantity, price)
+                SELECT $1, products.id, CASE WHEN products.stock IS NULL THEN 1 ELSE $3 END,
+                COALESCE(active_sales.price, products.price)
+                FROM products LEFT JOIN active_sales ON active_sales.product_id = products.id
+                WHERE products.id = $2 AND products.deleted_at IS NULL AND products.seller_id != $1
+                AND (products.stock IS NULL OR products.stock >= $3)
+                ON CONFLICT (user_id, product_id) DO UPDATE SET quantity = EXCLUDED.quantity, price = EXCLUDED.price
+            "#,
+            id, input.product_id, quantity
+        ).execute(&self.pool).await?;
+        Ok(added == 1)
+    }
+
+    pub async fn db_remove_from_cart(&self, id: i64, product_id: i64) -> Result<bool, sqlx::Error> {
+        let removed = sqlx::query!(
+            r#"DELETE FROM cart_items WHERE user_id = $1 AND product_id = $2"#, id, product_id
+        ).execute(&self.pool).await?;
+        Ok(removed == 1)
+    }
+
+    pub async fn db_get_cart(&self, id: i64) -> Result<Vec<CartItem>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        Database::_prune_cart(&mut tx, id).await?;
+        let mut sql_res = sqlx::query_as!(CartItem,
+        r#"
+            SELECT products.id AS product_id, products.seller_id, users.username, products.product_type, products.description,
+            cart_items.price, cart_items.quantity, products.stock, medias.path, medias.thumbnail_path, medias.media_type,
+            cart_items.added_at, is_entitled($1, products.id) AS entitled
+            FROM cart_items INNER JOIN products ON cart_items.product_id = products.id
+            INNER JOIN users ON products.seller_id = users.id INNER JOIN medias ON products.media_id = medias.id
+            WHERE cart_items.user_id = $1 ORDER BY cart_items.added_at ASC
+        "#, id).fetch_all(&mut tx).await?;
+        tx.commit().await?;
+        sql_res.iter_mut().for_each(CartItem::hide_locked_media);
+        Ok(sql_res)
+    }
+
+    // buy the whole cart at the prices the buyer saw, and empty it
+    pub async fn db_checkout_cart(&self, id: i64, input: CheckoutInput) -> Result<CheckoutStatus, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let pruned = Database::_prune_cart(&mut tx, id).await?;
+        let items = sqlx::query!(
+            r#"
+                SELECT cart_items.product_id, cart_items.quantity, cart_items.price,
+                COALESCE(active_sales.price, products.price) AS current_price
+                FROM cart_items INNER JOIN products ON cart_items.product_id = products.id
+                LEFT JOIN active_sales ON active_sales.product_id = products.id
+                WHERE cart_items.user_id = $1 FOR UPDATE OF cart_items, products
+            "#,
+            id
+        ).fetch_all(&mut tx).await?;
+        if items.is_empty() {
+            return Ok(CheckoutStatus::Refused)
+        }
+        if pruned || items.iter().any(|item| item.price != item.current_price) {
+            sqlx::query!(
+                r#"
+                    UPDATE cart_items SET price = COALESCE(active_sales.price, products.price)
+                    FROM products LEFT JOIN active_sales ON active_sales.product_id = products.id
+                    WHERE cart_items.product_id = products.id AND cart_items.user_id = $1
+                "#,
+                id
+            ).execute(&mut tx).await?;
+            tx.commit().await?;
+            return Ok(CheckoutStatus::CartChanged)
+        }
+
+        let mut products = Vec::new();
+        for item in items {
+            for _ in 0..item.quantity {
+                products.push(item.product_id);
+            }
+        }
+        if !Database::_buy_products(&mut tx, id, products, input.shipping_address, input.discount_code).await? {
+            return Ok(CheckoutStatus::Refused)
+        }
+        sqlx::query!(
+            r#"DELETE FROM cart_items WHERE user_id = $1"#, id
+        ).execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(CheckoutStatus::Done)
+    }
+
+    // drop the deleted and sold out products and the medias the user already has access to from the cart,
+    // and fit the quantities to the stock
+    // returns true if the cart changed
+    async fn _prune_cart(tx: &mut PgTransaction, id: i64) -> Result<bool, sqlx::Error> {
+        let removed = sqlx::query!(
+            r#"
+                DELETE FROM cart_items WHERE user_id = $1 AND product_id IN
+                (SELECT products.id FROM products WHERE products.deleted_at IS NOT NULL OR products.stock = 0
+                OR (products.product_type = 'MEDIA' AND is_entitled($1, products.id)))
+            "#,
+            id
+        ).execute(&mut *tx).await?;
+        let reduced = sqlx::query!(
+            r#"
+                UPDATE cart_items SET quantity = products.stock FROM products
+                WHERE cart_items.product_id = products.id AND cart_items.user_id = $1 AND cart_items.quantity > products.stock
+            "#,
+            id
+        ).execute(&mut *tx).await?;
+        Ok(removed + reduced > 0)
+    }
+
+    // bucket is day, week or month
+    pub async fn db_get_sales_stats(&self, id: i64, bucket: String) -> Result<SalesStats, sqlx::Error> {
+        let revenue = sqlx::query_as!(RevenueBucket,
+        r#"
+            SELECT date_trunc($2, purchases.created_at) AS period,
+            SUM(purchases.price)::BIGINT AS revenue, COUNT(*) AS sales
+            FROM purchases INNER JOIN products ON purchases.product_id = products.id
+            WHERE products.seller_id = $1
+            GROUP BY period ORDER BY period ASC
+        "#, id, bucket).fetch_all(&self.pool).await?;
+
+        let best_sellers = sqlx::query_as!(ProductSales,
+        r#"
+            SELECT products.id AS product_id, products.description, products.views,
+            COUNT(purchases.id) AS sales, COALESCE(SUM(purchases.price), 0)::BIGINT AS revenue,
+            CASE WHEN products.views > 0 THEN COUNT(purchases.id)::FLOAT8 / products.views ELSE 0 END AS conversion
+            FROM products LEFT JOIN purchases ON purchases.product_id = products.id
+            WHERE products.seller_id = $1
+            GROUP BY products.id ORDER BY sales DESC, revenue DESC LIMIT 10
+        "#, id).fetch_all(&self.pool).await?;
+
+        let totals = sqlx::query!(
+            r#"
+                SELECT COALESCE(SUM(products.views), 0)::BIGINT AS views,
+                (SELECT COUNT(*) FROM purchases INNER JOIN products ON purchases.product_id = products.id
+                WHERE products.seller_id = $1) AS sales
+                FROM products WHERE products.seller_id = $1
+            "#,
+            id
+        ).fetch_one(&self.pool).await?;
+        let conversion = if totals.views > 0 {
+            totals.sales as f64 / totals.views as f64
+        } else {
+            0.0
+        };
+
+        let follower_growth = sqlx::query_as!(FollowerBucket,
+        r#"
+            SELECT date_trunc($2, follows.created_at) AS period, COUNT(*) AS followers
+            FROM follows WHERE follows.followee_id = $1
+            GROUP BY period ORDER BY period ASC
+        "#, id, bucket).fetch_all(&self.pool).await?;
+
+        Ok(SalesStats {
+            revenue,
+            best_sellers,
+            views: totals.views,
+            sales: totals.sales,
+            conversion,
+            follower_growth,
+        })
+    }
+
+    pub async fn db_get_my_orders(&self, id: i64) -> Result<Vec<Order>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Order,
+            r#"SELECT * FROM orders WHERE buyer_id = $1 OR seller_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // only the seller ships, and only a paid order
+    // the escrow is released to the seller 14 days after shipping unless the buyer confirms or disputes first
+    pub async fn db_ship_order(&self, id: i64, order_id: i64, input: ShipOrderInput) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE orders SET status = 'SHIPPED', tracking_number = $1, release_after = NOW() + INTERVAL '14 days', updated_at = NOW()
+                WHERE id = $2 AND seller_id = $3 AND status = 'PAID'
+            "#,
+            input.tracking_number, order_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    // the buyer confirms the delivery, which releases the escrow to the seller
+    // confirming a disputed order closes the dispute
+    pub async fn db_confirm_order(&self, id: i64, order_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let updated = sqlx::query!(
+            r#"
+                UPDATE orders SET status = 'DELIVERED', updated_at = NOW()
+                WHERE id = $1 AND buyer_id = $2 AND status IN ('PAID', 'SHIPPED', 'DISPUTED')
+            "#,
+            order_id, id
+        ).execute(&mut tx).await?;
+        if updated != 1 {
+            return Ok(false)
+        }
+        Database::_release_escrow(&mut tx, order_id).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // a dispute freezes the escrow until the buyer confirms or the seller refunds
+    // a dispute still open after 30 days is refunded to the buyer
+    pub async fn db_dispute_order(&self, id: i64, order_id: i64) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE orders SET status = 'DISPUTED', escrow = 'FROZEN', release_after = NOW() + INTERVAL '30 days', updated_at = NOW()
+                WHERE id = $1 AND buyer_id = $2 AND status IN ('PAID', 'SHIPPED') AND escrow = 'HELD'
+            "#,
+            order_id, id
+        ).execute(&self.pool).await?;
+        Ok(updated == 1)
+    }
+
+    // the seller gives the escrow back to the buyer
+    pub async fn db_refund_order(&self, id: i64, order_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Order,
+            r#"
+                UPDATE orders SET status = 'REFUNDED', escrow = 'REFUNDED', updated_at = NOW()
+                WHERE id = $1 AND seller_id = $2 AND escrow IN ('HELD', 'FROZEN') RETURNING *
+            "#,
+            order_id, id
+        ).fetch_one(&mut tx).await;
+        let order = match Database::_handle_optional_result(sql_res)? {
+            Some(order) => order,
+            None => return Ok(false),
+        };
+        Database::_refund_escrow(&mut tx, &order).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // give the escrow of a refunded order back to the buyer
+    async fn _refund_escrow(tx: &mut PgTransaction, order: &Order) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE users SET quadreum_escrow = quadreum_escrow - $1 WHERE id = $2"#, order.price, order.seller_id
+        ).execute(&mut *tx).await?;
+        Database::_credit(tx, order.buyer_id, order.price, "REFUND", Some(order.product_id)).await?;
+        // the unit goes back on sale and the buyer no longer owns it
+        if let Some(purchase_id) = order.purchase_id {
+            sqlx::query!(r#"DELETE FROM purchases WHERE id = $1"#, purchase_id).execute(&mut *tx).await?;
+        }
+        sqlx::query!(
+            r#"UPDATE products SET stock = stock + 1 WHERE id = $1 AND stock IS NOT NULL"#, order.product_id
+        ).execute(&mut *tx).await?;
+        Ok(true)
+    }
+
+    // orders past their deadline: shipped and neither confirmed nor disputed, never shipped, or still disputed
+    pub async fn db_get_due_escrows(&self) -> Result<Vec<Order>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(Order,
+            r#"
+                SELECT * FROM orders WHERE status IN ('PAID', 'SHIPPED', 'DISPUTED') AND escrow IN ('HELD', 'FROZEN')
+                AND release_after <= NOW()
+            "#
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+
+    // refund the buyer of an order the seller didn't ship or a dispute nobody closed in time
+    pub async fn db_auto_refund_order(&self, order_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let sql_res = sqlx::query_as!(Order,
+            r#"
+                UPDATE orders SET status = 'REFUNDED', escrow = 'REFUNDED', updated_at = NOW()
+                WHERE id = $1 AND status IN ('PAID', 'DISPUTED') AND escrow IN ('HELD', 'FROZEN') AND release_after <= NOW()
+                RETURNING *
+            "#,
+            order_id
+        ).fetch_one(&mut tx).await;
+        let order = match Database::_handle_optional_result(sql_res)? {
+            Some(order) => order,
+            None => return Ok(false),
+        };
+        Database::_refund_escrow(&mut tx, &order).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn db_auto_release_order(&self, order_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let updated = sqlx::query!(
+            r#"
+                UPDATE orders SET status = 'DELIVERED', updated_at = NOW()
+                WHERE id = $1 AND status = 'SHIPPED' AND escrow = 'HELD' AND release_after <= NOW()
+            "#,
+            order_id
+        ).execute(&mut tx).await?;
+        if updated != 1 {
+            return Ok(false)
+        }
+        Database::_release_escrow(&mut tx, order_id).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn db_get_balance_history(&self, id: i64) -> Result<Vec<BalanceEntry>, sqlx::Error> {
+        let sql_res = sqlx::query_as!(BalanceEntry,
+            r#"SELECT * FROM balance_history WHERE user_id = $1 ORDER BY created_at DESC"#, id
+        ).fetch_all(&self.pool).await?;
+        Ok(sql_res)
+    }
+}
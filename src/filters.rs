@@ -2,6 +2,9 @@ use warp::Filter;
 use crate::json_extractor::*;
 use crate::database::*;
 use crate::handlers::*;
+use crate::models::*;
+use crate::view_counter::*;
+use crate::notifier::*;
 use warp::{
     filters::multipart::{FormData, Part},
     reject, Buf, Rejection
@@ -22,7 +25,17 @@ fn with_db(db: Database) -> impl Filter<Extract = (Database,), Error = std::conv
     warp::any().map(move || db.clone())
 }
 
-pub fn rest_swell(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+/// Make the view counter accessible within filter
+fn with_view_counter(views: ViewCounter) -> impl Filter<Extract = (ViewCounter,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || views.clone())
+}
+
+/// Make the websocket connections accessible within filter
+fn with_notifier(notifier: Notifier) -> impl Filter<Extract = (Notifier,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || notifier.clone())
+}
+
+pub fn rest_swell(db: Database, views: ViewCounter, notifier: Notifier) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     rest_register(db.clone())
         .or(rest_get_user_by_id(db.clone()))
         .or(rest_get_my_profile(db.clone()))
@@ -34,17 +47,92 @@ pub fn rest_swell(db: Database) -> impl Filter<Extract = impl warp::Reply, Error
         .or(rest_get_all_messages(db.clone()))
         .or(rest_get_my_threads(db.clone()))
         .or(rest_send_message(db.clone()))
+        .or(rest_send_media_message(db.clone()))
+        .or(rest_create_thread(db.clone()))
+        .or(rest_get_thread_messages(db.clone()))
+        .or(rest_mark_thread_read(db.clone()))
+        .or(rest_add_participant(db.clone()))
+        .or(rest_leave_thread(db.clone()))
+        .or(rest_hide_thread(db.clone()))
+        .or(rest_accept_thread(db.clone()))
+        .or(rest_get_message_requests(db.clone()))
+        .or(rest_edit_message(db.clone()))
+        .or(rest_delete_message(db.clone()))
+        .or(rest_get_unread_count(db.clone()))
+        .or(rest_ws(db.clone(), notifier))
         .or(rest_search(db.clone()))
         .or(rest_get_followers(db.clone()))
         .or(rest_get_followees(db.clone()))
         .or(rest_follow(db.clone()))
         .or(rest_unfollow(db.clone()))
+        .or(rest_block(db.clone()))
+        .or(rest_unblock(db.clone()))
+        .or(rest_get_blocked_users(db.clone()))
         .or(rest_upload_profile(db.clone()))
         .or(rest_buy_products(db.clone()))
+        .or(rest_add_to_cart(db.clone()))
+        .or(rest_remove_from_cart(db.clone()))
+        .or(rest_get_cart(db.clone()))
+        .or(rest_checkout_cart(db.clone()))
+        .or(rest_add_discount_code(db.clone()))
+        .or(rest_get_my_discount_codes(db.clone()))
+        .or(rest_delete_discount_code(db.clone()))
+        .or(rest_add_sale_price(db.clone()))
+        .or(rest_get_sale_prices(db.clone()))
+        .or(rest_delete_sale_price(db.clone()))
+        .or(rest_create_auction(db.clone()))
+        .or(rest_get_auction(db.clone()))
+        .or(rest_place_bid(db.clone()))
+        .or(rest_get_bids(db.clone()))
+        .or(rest_make_offer(db.clone()))
+        .or(rest_get_my_offers(db.clone()))
+        .or(rest_accept_offer(db.clone()))
+        .or(rest_decline_offer(db.clone()))
+        .or(rest_counter_offer(db.clone()))
+        .or(rest_set_subscription_tier(db.clone()))
+        .or(rest_delete_subscription_tier(db.clone()))
+        .or(rest_get_subscription_tier(db.clone()))
+        .or(rest_subscribe(db.clone()))
+        .or(rest_unsubscribe(db.clone()))
+        .or(rest_get_my_subscriptions(db.clone()))
+        .or(rest_get_product_medias(db.clone()))
+        .or(rest_tip_user(db.clone()))
+        .or(rest_tip_product(db.clone()))
+        .or(rest_get_received_tips(db.clone()))
+        .or(rest_get_my_orders(db.clone()))
+        .or(rest_get_sales_stats(db.clone()))
+        .or(rest_ship_order(db.clone()))
+        .or(rest_confirm_order(db.clone()))
+        .or(rest_dispute_order(db.clone()))
+        .or(rest_refund_order(db.clone()))
+        .or(rest_get_balance_history(db.clone()))
+        .or(rest_get_product(db.clone()))
+        .or(rest_edit_product(db.clone()))
+        .or(rest_delete_product(db.clone()))
+        .or(rest_get_product_splits(db.clone()))
+        .or(rest_set_product_splits(db.clone()))
+        .or(rest_like_product(db.clone()))
+        .or(rest_unlike_product(db.clone()))
+        .or(rest_get_my_likes(db.clone()))
+        .or(rest_view_product(views, db.clone()))
+        .or(rest_add_comment(db.clone()))
+        .or(rest_get_comments(db.clone()))
+        .or(rest_edit_comment(db.clone()))
+        .or(rest_delete_comment(db.clone()))
         //.or(rest_send_quadreum(db.clone()))
-        .or(warp::path("files")
-            .and(warp::get())
-            .and(warp::fs::dir("files/")))
+        .or(rest_get_file(db.clone()))
+}
+
+// the files of a paid product are only served to the users entitled to it
+pub fn rest_get_file(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("files")
+        .and(warp::get())
+        .and(warp::path::peek())
+        .and(warp::header::optional::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(check_file_access)
+        .untuple_one()
+        .and(warp::fs::dir("files/"))
 }
 
 pub fn rest_register(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -109,6 +197,30 @@ pub fn rest_unfollow(db: Database) -> impl Filter<Extract = impl warp::Reply, Er
         .and_then(handle_unfollow)
 }
 
+pub fn rest_block(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "block")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_block)
+}
+
+pub fn rest_unblock(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "block")
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_unblock)
+}
+
+pub fn rest_get_blocked_users(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "blocks")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_blocked_users)
+}
+
 pub fn rest_search(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("search")
         .and(warp::post())
@@ -133,6 +245,7 @@ pub fn rest_get_products_feed(db: Database) -> impl Filter<Extract = impl warp::
     warp::path!("get_products_feed")
         .and(warp::get())
         .and(warp::header::<String>("Authorization"))
+        .and(warp::query::<FeedQuery>())
         .and(with_db(db))
         .and_then(handle_get_products_feed)
 }
@@ -172,6 +285,118 @@ Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         .and_then(handle_send_message)
 }
 
+pub fn rest_send_media_message(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("send_media_message")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(warp::body::content_length_limit(1024 * 2000000))
+        .and(warp::multipart::form().max_length(1024 * 2000000))
+        .and_then(deserialize_form_message)
+        .and(with_db(db))
+        .and_then(save_message_media)
+}
+
+pub fn rest_create_thread(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_new_thread())
+        .and(with_db(db))
+        .and_then(handle_create_thread)
+}
+
+pub fn rest_get_thread_messages(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "messages")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_thread_messages)
+}
+
+pub fn rest_mark_thread_read(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "read")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_mark_as_read())
+        .and(with_db(db))
+        .and_then(handle_mark_thread_read)
+}
+
+pub fn rest_add_participant(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "participants")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_add_participant())
+        .and(with_db(db))
+        .and_then(handle_add_participant)
+}
+
+pub fn rest_leave_thread(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "participants")
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_leave_thread)
+}
+
+pub fn rest_hide_thread(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "hide")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_hide_thread)
+}
+
+pub fn rest_accept_thread(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("threads" / i64 / "accept")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_accept_thread)
+}
+
+pub fn rest_get_message_requests(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "message_requests")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_message_requests)
+}
+
+pub fn rest_edit_message(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("messages" / i64)
+        .and(warp::put())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_edit_message())
+        .and(with_db(db))
+        .and_then(handle_edit_message)
+}
+
+pub fn rest_delete_message(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("messages" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_message)
+}
+
+pub fn rest_get_unread_count(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "unread_count")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_unread_count)
+}
+
+pub fn rest_ws(db: Database, notifier: Notifier) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("ws")
+        .and(warp::header::<String>("Authorization"))
+        .and(warp::ws())
+        .and(with_db(db))
+        .and(with_notifier(notifier))
+        .and_then(handle_ws)
+}
+
 pub fn rest_get_my_threads(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("get_my_threads")
         .and(warp::get())
@@ -200,6 +425,417 @@ pub fn rest_buy_products(db: Database) -> impl Filter<Extract = impl warp::Reply
         .and_then(handle_buy_products)
 }
 
+pub fn rest_add_discount_code(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("discount_codes")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_discount_code())
+        .and(with_db(db))
+        .and_then(handle_add_discount_code)
+}
+
+pub fn rest_get_my_discount_codes(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "discount_codes")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_my_discount_codes)
+}
+
+pub fn rest_delete_discount_code(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("discount_codes" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_discount_code)
+}
+
+pub fn rest_add_sale_price(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "sales")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_sale_price())
+        .and(with_db(db))
+        .and_then(handle_add_sale_price)
+}
+
+pub fn rest_get_sale_prices(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "sales")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_sale_prices)
+}
+
+pub fn rest_delete_sale_price(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sales" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_sale_price)
+}
+
+pub fn rest_create_auction(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "auction")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_auction())
+        .and(with_db(db))
+        .and_then(handle_create_auction)
+}
+
+pub fn rest_get_auction(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "auction")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_auction)
+}
+
+pub fn rest_place_bid(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "bids")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_bid())
+        .and(with_db(db))
+        .and_then(handle_place_bid)
+}
+
+pub fn rest_get_bids(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "bids")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_bids)
+}
+
+pub fn rest_make_offer(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "offers")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_offer())
+        .and(with_db(db))
+        .and_then(handle_make_offer)
+}
+
+pub fn rest_get_my_offers(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "offers")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_my_offers)
+}
+
+pub fn rest_accept_offer(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("offers" / i64 / "accept")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_accept_offer)
+}
+
+pub fn rest_decline_offer(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("offers" / i64 / "decline")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_decline_offer)
+}
+
+pub fn rest_counter_offer(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("offers" / i64 / "counter")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_counter_offer())
+        .and(with_db(db))
+        .and_then(handle_counter_offer)
+}
+
+pub fn rest_set_subscription_tier(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "subscription_tier")
+        .and(warp::put())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_subscription_tier())
+        .and(with_db(db))
+        .and_then(handle_set_subscription_tier)
+}
+
+pub fn rest_delete_subscription_tier(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "subscription_tier")
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_subscription_tier)
+}
+
+pub fn rest_get_subscription_tier(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "subscription_tier")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_subscription_tier)
+}
+
+pub fn rest_subscribe(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "subscription")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_subscribe)
+}
+
+pub fn rest_unsubscribe(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "subscription")
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_unsubscribe)
+}
+
+pub fn rest_get_my_subscriptions(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "subscriptions")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_my_subscriptions)
+}
+
+pub fn rest_get_product_medias(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "medias")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_product_medias)
+}
+
+pub fn rest_tip_user(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / i64 / "tip")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_tip())
+        .and(with_db(db))
+        .and_then(handle_tip_user)
+}
+
+pub fn rest_tip_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "tip")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_tip())
+        .and(with_db(db))
+        .and_then(handle_tip_product)
+}
+
+pub fn rest_get_received_tips(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "tips")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_received_tips)
+}
+
+pub fn rest_add_to_cart(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cart")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_add_to_cart())
+        .and(with_db(db))
+        .and_then(handle_add_to_cart)
+}
+
+pub fn rest_remove_from_cart(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cart" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_remove_from_cart)
+}
+
+pub fn rest_get_cart(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cart")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_cart)
+}
+
+pub fn rest_checkout_cart(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cart" / "checkout")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_checkout())
+        .and(with_db(db))
+        .and_then(handle_checkout_cart)
+}
+
+pub fn rest_get_sales_stats(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "sales" / "stats")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(warp::query::<SalesStatsQuery>())
+        .and(with_db(db))
+        .and_then(handle_get_sales_stats)
+}
+
+pub fn rest_get_my_orders(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "orders")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_my_orders)
+}
+
+pub fn rest_ship_order(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("orders" / i64 / "ship")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_ship_order())
+        .and(with_db(db))
+        .and_then(handle_ship_order)
+}
+
+pub fn rest_confirm_order(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("orders" / i64 / "confirm")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_confirm_order)
+}
+
+pub fn rest_dispute_order(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("orders" / i64 / "dispute")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_dispute_order)
+}
+
+pub fn rest_refund_order(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("orders" / i64 / "refund")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_refund_order)
+}
+
+pub fn rest_get_balance_history(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "balance_history")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_balance_history)
+}
+
+pub fn rest_get_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64)
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_product)
+}
+
+pub fn rest_edit_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64)
+        .and(warp::patch())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_edit_product())
+        .and(with_db(db))
+        .and_then(handle_edit_product)
+}
+
+pub fn rest_delete_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_product)
+}
+
+pub fn rest_get_product_splits(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "splits")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_product_splits)
+}
+
+pub fn rest_set_product_splits(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "splits")
+        .and(warp::put())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_splits())
+        .and(with_db(db))
+        .and_then(handle_set_product_splits)
+}
+
+pub fn rest_like_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "like")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_like_product)
+}
+
+pub fn rest_unlike_product(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "like")
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_unlike_product)
+}
+
+pub fn rest_view_product(views: ViewCounter, db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "view")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_view_counter(views))
+        .and(with_db(db))
+        .and_then(handle_view_product)
+}
+
+pub fn rest_get_my_likes(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "likes")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_get_my_likes)
+}
+
+pub fn rest_add_comment(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "comments")
+        .and(warp::post())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_comment())
+        .and(with_db(db))
+        .and_then(handle_add_comment)
+}
+
+pub fn rest_get_comments(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("products" / i64 / "comments")
+        .and(warp::get())
+        .and(warp::header::<String>("Authorization"))
+        .and(warp::query::<CommentsQuery>())
+        .and(with_db(db))
+        .and_then(handle_get_comments)
+}
+
+pub fn rest_edit_comment(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("comments" / i64)
+        .and(warp::patch())
+        .and(warp::header::<String>("Authorization"))
+        .and(json_body_edit_comment())
+        .and(with_db(db))
+        .and_then(handle_edit_comment)
+}
+
+pub fn rest_delete_comment(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("comments" / i64)
+        .and(warp::delete())
+        .and(warp::header::<String>("Authorization"))
+        .and(with_db(db))
+        .and_then(handle_delete_comment)
+}
+
 /*
 pub fn rest_send_quadreum(db: Database) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("send_quadreum")
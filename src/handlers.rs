@@ -8,14 +8,17 @@ use warp::{
     reject, Buf, Rejection,
 };
 
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
 use serde::de::DeserializeOwned;
 use serde_json;
 use uuid::Uuid;
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
 use tokio::fs::File;
 use tokio::prelude::*;
 use crate::ffmpeg_utils::*;
+use crate::view_counter::*;
+use crate::notifier::*;
 
 /*
 use warp::http::StatusCode;
@@ -134,7 +137,8 @@ pub async fn handle_follow(user_id: i64, id: String, db: Database) -> Result<imp
         Ok(id) => {
             let res = db.follow(user_id, id).await;
             match res {
-                Ok(_) => Ok(StatusCode::CREATED),
+                Ok(true) => Ok(StatusCode::CREATED),
+                Ok(false) => Ok(StatusCode::FORBIDDEN),
                 Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR),
             }
         }
@@ -155,6 +159,93 @@ pub async fn handle_unfollow(user_id: i64, id: String, db: Database) -> Result<i
 }
 
 
+pub async fn handle_block(user_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) if id == user_id => {
+            code = 400;
+            data = String::from("You can't block yourself")
+        }
+        Ok(id) => {
+            let sql_res = db.block(user_id, id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("User blocked");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("User not found or already blocked");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_unblock(user_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.unblock(user_id, id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("User unblocked");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("User not blocked");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_blocked_users(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.get_blocked_users(id).await;
+            match sql_res {
+                Ok(users) => {
+                    code = 200;
+                    data = serde_json::to_string(&users).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
 pub async fn handle_get_users_by_pattern(id: String, pattern: Pattern, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
@@ -265,9 +356,15 @@ pub async fn deserialize_form_data(id: String, form_data: FormData) -> Result<Re
                 "price" => {
                     let part_bytes = part.data().await.unwrap().unwrap();
                     let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
-                    let value = value.parse::<i64>().unwrap();
+                    let value = value.parse::<Amount>().ok();
                     PartType::Price(value)
                 }
+                "stock" => {
+                    let part_bytes = part.data().await.unwrap().unwrap();
+                    let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
+                    let value = value.parse::<i64>().ok().filter(|stock| *stock >= 0);
+                    PartType::Stock(value)
+                }
                 "media_type" => {
                     let part_bytes = part.data().await.unwrap().unwrap();
                     let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
@@ -278,6 +375,12 @@ pub async fn deserialize_form_data(id: String, form_data: FormData) -> Result<Re
                     let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
                     PartType::ProductType(value)
                 }
+                "cover" => {
+                    let part_bytes = part.data().await.unwrap().unwrap();
+                    let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
+                    let value = value.parse::<usize>().ok();
+                    PartType::Cover(value)
+                }
                 _ => PartType::NoFormData,
             }
         })
@@ -287,7 +390,10 @@ pub async fn deserialize_form_data(id: String, form_data: FormData) -> Result<Re
     for part in parts {
         match part {
             PartType::FilePart(file_part) => {
-                result_data.file_part = Some(file_part);
+                result_data.file_parts.push(file_part);
+            }
+            PartType::Cover(cover) => {
+                result_data.cover = cover;
             }
 
             PartType::Description(description) => {
@@ -296,6 +402,9 @@ pub async fn deserialize_form_data(id: String, form_data: FormData) -> Result<Re
             PartType::Price(price) => {
                 result_data.price = price;
             }
+            PartType::Stock(stock) => {
+                result_data.stock = Some(stock);
+            }
             PartType::SellerId(id) => {
                 result_data.seller_id = id;
             }
@@ -308,41 +417,209 @@ pub async fn deserialize_form_data(id: String, form_data: FormData) -> Result<Re
             PartType::NoFormData => (),
         };
     }
-    println!("result data: {} {} {}", result_data.seller_id, result_data.price, result_data.description);
+    println!("result data: {} {:?} {}", result_data.seller_id, result_data.price, result_data.description);
     Ok(result_data)
 }
 
 pub async fn save_media_file(product: ResultData, db: Database) -> Result<impl warp::Reply, Infallible> {
     println!("IN SAVE MEDIA");
+    let cover = match product.cover {
+        Some(cover) if cover < product.file_parts.len() => cover,
+        _ => return Ok(StatusCode::BAD_REQUEST),
+    };
+    let price = match product.price {
+        Some(price) if !price.is_negative() => price,
+        _ => return Ok(StatusCode::BAD_REQUEST),
+    };
+    // a MEDIA product can be sold to any number of buyers
+    let stock = match product.stock {
+        Some(None) => return Ok(StatusCode::BAD_REQUEST),
+        stock if product.product_type == "REAL" => Some(stock.flatten().unwrap_or(1)),
+        _ => None,
+    };
+    let mut medias = Vec::new();
+    for file_part in product.file_parts {
+        medias.push(write_media_file(file_part, &product.media_type).await);
+    }
+    let res = db.db_add_product(product.seller_id,
+                                product.description, price.0,
+                                product.product_type, stock,
+                                medias.clone(), cover).await;
+    if let Ok(true) = res {
+        for media in medias {
+            if !media.thumbnail_path.is_empty() {
+                let status = create_thumbnail(media.path, media.thumbnail_path).await;
+            }
+        }
+        return Ok(StatusCode::CREATED)
+    }
+    // the product was not added, nothing refers to the files written
+    for media in medias {
+        let _ = tokio::fs::remove_file(media.path).await;
+    }
+    match res {
+        Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR),
+        _ => Ok(StatusCode::FORBIDDEN),
+    }
+}
+
+// a gallery can mix photos and videos, default_media_type is used when the part has no content type
+async fn write_media_file(mut file_part: Part, default_media_type: &str) -> NewMedia {
+    let media_type = match file_part.content_type() {
+        Some(content_type) if content_type.starts_with("video/") => String::from("VIDEO"),
+        Some(content_type) if content_type.starts_with("image/") => String::from("IMAGE"),
+        _ => default_media_type.to_string(),
+    };
     let thumbnail_path;
     let uuid = Uuid::new_v4().to_string();
-    let extension = if product.media_type == "VIDEO" {
+    let extension = if media_type == "VIDEO" {
         thumbnail_path = format!("files/{}.png", uuid);
         String::from("mp4")
     } else {
         thumbnail_path = String::from("");
         String::from("jpg")
     };
-    let file_path = format!("files/{}.{}", uuid, extension.clone());
-    let data_buf = product.file_part.unwrap().data().await.unwrap().unwrap();
+    let file_path = format!("files/{}.{}", uuid, extension);
+    let data_buf = file_part.data().await.unwrap().unwrap();
     let data_bytes = data_buf.bytes();
     let mut file = File::create(file_path.clone()).await.unwrap();
     file.write_all(data_bytes).await.unwrap();
-    let res = db.db_add_product(product.seller_id,
-                                product.description, product.price,
-                                product.product_type,
-                                product.media_type,
-                                file_path.clone(), thumbnail_path.clone()).await;
-    if !thumbnail_path.is_empty() {
-        let status = create_thumbnail(file_path.clone(), thumbnail_path.clone()).await;
+    NewMedia { path: file_path, thumbnail_path, media_type }
+}
+
+pub async fn deserialize_form_message(id: String, form_data: FormData) -> Result<MessageData, Rejection> {
+    let mut result_data = MessageData::new();
+    result_data.sender = id.parse::<i64>().ok();
+    let parts: Vec<MessagePartType> = form_data
+        .then(|part| async {
+            let mut part = part.unwrap();
+            match part.name() {
+                "content" => MessagePartType::FilePart(part),
+                "thread_id" => {
+                    let part_bytes = part.data().await.unwrap().unwrap();
+                    let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
+                    match value.parse::<i64>() {
+                        Ok(value) => MessagePartType::ThreadId(value),
+                        Err(_) => MessagePartType::NoFormData,
+                    }
+                }
+                "receiver" => {
+                    let part_bytes = part.data().await.unwrap().unwrap();
+                    let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
+                    match value.parse::<i64>() {
+                        Ok(value) => MessagePartType::Receiver(value),
+                        Err(_) => MessagePartType::NoFormData,
+                    }
+                }
+                "caption" => {
+                    let part_bytes = part.data().await.unwrap().unwrap();
+                    let value = std::str::from_utf8(part_bytes.bytes()).unwrap().to_string();
+                    MessagePartType::Caption(value)
+                }
+                _ => MessagePartType::NoFormData,
+            }
+        })
+        .collect::<Vec<MessagePartType>>()
+        .await;
+
+    for part in parts {
+        match part {
+            MessagePartType::FilePart(file_part) => {
+                result_data.file_part = Some(file_part);
+            }
+            MessagePartType::ThreadId(thread_id) => {
+                result_data.thread_id = Some(thread_id);
+            }
+            MessagePartType::Receiver(receiver) => {
+                result_data.receiver = Some(receiver);
+            }
+            MessagePartType::Caption(caption) => {
+                result_data.caption = caption;
+            }
+            MessagePartType::NoFormData => (),
+        };
     }
+    Ok(result_data)
+}
 
-    match res {
-        Ok(_) => Ok(StatusCode::CREATED),
-        Err(e) => Ok(StatusCode::INTERNAL_SERVER_ERROR)
+pub async fn save_message_media(message: MessageData, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match (message.sender, message.file_part) {
+        (None, _) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        (Some(_), None) => {
+            code = 400;
+            data = String::from("Missing attachment")
+        }
+        (Some(_), Some(_)) if message.thread_id.is_none() && message.receiver.is_none() => {
+            code = 400;
+            data = String::from("Missing thread_id or receiver")
+        }
+        (Some(sender), Some(file_part)) => {
+            let media = write_media_file(file_part, "IMAGE").await;
+            let sql_res = db.db_add_media_message(sender,
+                                                  message.thread_id,
+                                                  message.receiver,
+                                                  message.caption,
+                                                  media.clone()).await;
+            match sql_res {
+                Ok(true) => {
+                    if !media.thumbnail_path.is_empty() {
+                        let _ = create_thumbnail(media.path, media.thumbnail_path).await;
+                    }
+                    code = 201;
+                    data = String::from("Message sent")
+                }
+                Ok(false) => {
+                    // the message was refused, nobody can reach the file
+                    let _ = tokio::fs::remove_file(media.path).await;
+                    code = 403;
+                    data = String::from("You can't send messages in this thread")
+                }
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(media.path).await;
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+// rejects the request when the file is the content of a product the user is not entitled to
+pub async fn check_file_access(file: warp::path::Peek, id: Option<String>, db: Database) -> Result<(), Rejection> {
+    let id = id.and_then(|id| id.parse::<i64>().ok());
+    let path = match served_path(file.as_str()) {
+        Some(path) => path,
+        None => return Err(reject::not_found()),
+    };
+    match db.db_can_access_file(id, path).await {
+        Ok(true) => Ok(()),
+        _ => Err(reject::not_found()),
     }
 }
 
+// the file warp::fs::dir serves for the tail of the request, written like the paths of the medias table
+// None when fs::dir refuses the tail too
+fn served_path(tail: &str) -> Option<String> {
+    let tail = percent_decode_str(tail).decode_utf8().ok()?;
+    let mut path = String::from("files");
+    for segment in tail.split('/') {
+        if segment.starts_with("..") || segment.contains('\\') {
+            return None
+        }
+        if !segment.is_empty() && segment != "." {
+            path.push('/');
+            path.push_str(segment);
+        }
+    }
+    Some(path)
+}
+
 pub async fn deserialize_form_profile(id: String, form_data: FormData) -> Result<ProfileData, Rejection> {
     println!("In deserialize");
     let mut result_data = ProfileData::new();
@@ -411,16 +688,21 @@ pub async fn save_profile(profile: ProfileData, db: Database) -> Result<impl war
     }
 }
 
-pub async fn handle_get_products_feed(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+pub async fn handle_get_products_feed(id: String, query: FeedQuery, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
+    let sort = query.sort.unwrap_or_else(|| String::from("new"));
     match id.parse::<i64>() {
         Err(_) => {
             code = 403;
             data = String::from("Bad token format")
         }
+        Ok(_) if sort != "trending" && sort != "new" && sort != "top" => {
+            code = 400;
+            data = String::from("Unknown sort, expected trending, new or top")
+        }
         Ok(id) => {
-            let sql_res = db.db_get_products_feed(id).await;
+            let sql_res = db.db_get_products_feed(id, sort).await;
             match sql_res {
                 Ok(feeds) => {
                     code = 200;
@@ -470,7 +752,7 @@ pub async fn handle_get_products_feed_by_user(user_id: i64, id: String, db: Data
             data = String::from("Bad token format")
         }
         Ok(id) => {
-            let sql_res = db.db_get_my_products_feed(user_id).await;
+            let sql_res = db.db_get_products_feed_by_user(id, user_id).await;
             match sql_res {
                 Ok(feeds) => {
                     code = 200;
@@ -486,7 +768,7 @@ pub async fn handle_get_products_feed_by_user(user_id: i64, id: String, db: Data
     Ok(warp::reply::json(&Response { code, data }))
 }
 
-pub async fn handle_get_my_threads(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+pub async fn handle_get_product(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
     match id.parse::<i64>() {
@@ -495,11 +777,15 @@ pub async fn handle_get_my_threads(id: String, db: Database) -> Result<impl warp
             data = String::from("Bad token format")
         }
         Ok(id) => {
-            let sql_res = db.db_get_all_threads(id).await;
+            let sql_res = db.db_get_product_detail(id, product_id).await;
             match sql_res {
-                Ok(threads) => {
+                Ok(Some(product)) => {
                     code = 200;
-                    data = serde_json::to_string(&threads).unwrap();
+                    data = serde_json::to_string(&product).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product not found");
                 }
                 Err(e) => {
                     code = 403;
@@ -511,7 +797,7 @@ pub async fn handle_get_my_threads(id: String, db: Database) -> Result<impl warp
     Ok(warp::reply::json(&Response { code, data }))
 }
 
-pub async fn handle_get_all_messages(id: String, input: AllMessagesInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+pub async fn handle_edit_product(product_id: i64, id: String, input: EditProductInput, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
     match id.parse::<i64>() {
@@ -519,21 +805,24 @@ pub async fn handle_get_all_messages(id: String, input: AllMessagesInput, db: Da
             code = 403;
             data = String::from("Bad token format")
         }
+        Ok(_) if input.price.map_or(false, |price| price.is_negative()) => {
+            code = 400;
+            data = String::from("Price must be positive")
+        }
         Ok(id) => {
-            if id != input.user1 && id != input.user2 {
-                code = 401;
-                data = String::from("Unauthorized")
-            } else {
-                let sql_res = db.db_get_all_messages(input).await;
-                match sql_res {
-                    Ok(messages) => {
-                        code = 200;
-                        data = serde_json::to_string(&messages).unwrap();
-                    }
-                    Err(e) => {
-                        code = 403;
-                        data = format!("{}", e);
-                    }
+            let sql_res = db.db_edit_product(id, product_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Product edited");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Product not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
                 }
             }
         }
@@ -541,7 +830,7 @@ pub async fn handle_get_all_messages(id: String, input: AllMessagesInput, db: Da
     Ok(warp::reply::json(&Response { code, data }))
 }
 
-pub async fn handle_send_message(id: String, input: SendMessageInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+pub async fn handle_delete_product(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
     match id.parse::<i64>() {
@@ -550,11 +839,22 @@ pub async fn handle_send_message(id: String, input: SendMessageInput, db: Databa
             data = String::from("Bad token format")
         }
         Ok(id) => {
-            let sql_res = db.db_add_message(id,input).await;
+            let sql_res = db.db_delete_product(id, product_id).await;
             match sql_res {
-                Ok(res) => {
+                Ok(Some(medias)) => {
+                    // the medias were never sold, nobody needs their files anymore
+                    for media in medias {
+                        let _ = tokio::fs::remove_file(media.path).await;
+                        if !media.thumbnail_path.is_empty() {
+                            let _ = tokio::fs::remove_file(media.thumbnail_path).await;
+                        }
+                    }
                     code = 200;
-                    data = serde_json::to_string(&res).unwrap();
+                    data = String::from("Product deleted");
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product not found");
                 }
                 Err(e) => {
                     code = 403;
@@ -563,24 +863,60 @@ pub async fn handle_send_message(id: String, input: SendMessageInput, db: Databa
             }
         }
     }
+    Ok(warp::reply::json(&Response { code, data }))
+}
 
+pub async fn handle_get_product_splits(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_product_splits(product_id).await;
+            match sql_res {
+                Ok(splits) => {
+                    code = 200;
+                    data = serde_json::to_string(&splits).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
     Ok(warp::reply::json(&Response { code, data }))
 }
 
-pub async fn handle_buy_products(id: String, buy_products: BuyProducts, db: Database) -> Result<impl warp::Reply, Infallible> {
+pub async fn handle_set_product_splits(product_id: i64, id: String, input: SplitsInput, db: Database) -> Result<impl warp::Reply, Infallible> {
     let code;
     let data;
+    let total_bps: i64 = input.splits.iter().map(|split| split.bps).sum();
+    let valid = input.splits.iter().all(|split| {
+        split.bps > 0 && (split.kind == "REFERRAL" || split.kind == "CO_CREATOR")
+    });
     match id.parse::<i64>() {
         Err(_) => {
             code = 403;
             data = String::from("Bad token format")
         }
+        Ok(_) if !valid || total_bps > 10000 => {
+            code = 400;
+            data = String::from("Splits must be REFERRAL or CO_CREATOR and share at most 10000 bps")
+        }
         Ok(id) => {
-            let sql_res = db.db_buy_products(id,buy_products).await;
+            let sql_res = db.db_set_product_splits(id, product_id, input).await;
             match sql_res {
-                Ok(res) => {
+                Ok(true) => {
                     code = 200;
-                    data = serde_json::to_string(&res).unwrap();
+                    data = String::from("Splits updated");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Product not found");
                 }
                 Err(e) => {
                     code = 403;
@@ -589,7 +925,1734 @@ pub async fn handle_buy_products(id: String, buy_products: BuyProducts, db: Data
             }
         }
     }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_like_product(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    match id.parse::<i64>() {
+        Err(_) => Ok(StatusCode::FORBIDDEN),
+        Ok(id) => {
+            let res = db.db_like_product(id, product_id).await;
+            match res {
+                Ok(true) => Ok(StatusCode::CREATED),
+                Ok(false) => Ok(StatusCode::NOT_FOUND),
+                Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+    }
+}
+
+pub async fn handle_unlike_product(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    match id.parse::<i64>() {
+        Err(_) => Ok(StatusCode::FORBIDDEN),
+        Ok(id) => {
+            let res = db.db_unlike_product(id, product_id).await;
+            match res {
+                Ok(_) => Ok(StatusCode::OK),
+                Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+    }
+}
+
+pub async fn handle_view_product(product_id: i64, id: String, views: ViewCounter, db: Database) -> Result<impl warp::Reply, Infallible> {
+    match id.parse::<i64>() {
+        Err(_) => Ok(StatusCode::FORBIDDEN),
+        Ok(id) => {
+            match db.db_is_product_listed(product_id).await {
+                Ok(true) => (),
+                Ok(false) => return Ok(StatusCode::NOT_FOUND),
+                Err(_) => return Ok(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+            if views.record(id, product_id) {
+                Ok(StatusCode::CREATED)
+            } else {
+                Ok(StatusCode::OK)
+            }
+        }
+    }
+}
 
+pub async fn handle_get_my_likes(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_liked_products(id).await;
+            match sql_res {
+                Ok(feeds) => {
+                    code = 200;
+                    data = serde_json::to_string(&feeds).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_add_comment(product_id: i64, id: String, input: CommentInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_comment(id, product_id, input).await;
+            match sql_res {
+                Ok(Some(comment)) => {
+                    code = 200;
+                    data = serde_json::to_string(&comment).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product or parent comment not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_comments(product_id: i64, id: String, query: CommentsQuery, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_comments(product_id, query).await;
+            match sql_res {
+                Ok(comments) => {
+                    code = 200;
+                    data = serde_json::to_string(&comments).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_edit_comment(comment_id: i64, id: String, input: EditCommentInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_edit_comment(id, comment_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Comment edited");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Comment not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_delete_comment(comment_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_delete_comment(id, comment_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Comment deleted");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Comment not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_my_threads(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_all_threads(id).await;
+            match sql_res {
+                Ok(threads) => {
+                    code = 200;
+                    data = serde_json::to_string(&threads).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_all_messages(id: String, input: AllMessagesInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            if id != input.user1 && id != input.user2 {
+                code = 401;
+                data = String::from("Unauthorized")
+            } else {
+                let sql_res = db.db_get_all_messages(input).await;
+                match sql_res {
+                    Ok(messages) => {
+                        code = 200;
+                        data = serde_json::to_string(&messages).unwrap();
+                    }
+                    Err(e) => {
+                        code = 403;
+                        data = format!("{}", e);
+                    }
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_send_message(id: String, input: SendMessageInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_message(id,input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = serde_json::to_string(&true).unwrap();
+                }
+                Ok(false) => {
+                    code = 403;
+                    data = String::from("You can't send messages in this thread")
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_edit_message(message_id: i64, id: String, input: EditMessageInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.content.trim().is_empty() => {
+            code = 400;
+            data = String::from("Message content can't be empty")
+        }
+        Ok(id) => {
+            let sql_res = db.db_edit_message(id, message_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Message edited");
+                }
+                Ok(false) => {
+                    code = 403;
+                    data = String::from("Only your messages of the last 15 minutes can be edited");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_delete_message(message_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_delete_message(id, message_id).await;
+            match sql_res {
+                Ok(Some(medias)) => {
+                    // a deleted attachment must not stay reachable by its url
+                    for media in medias {
+                        let _ = tokio::fs::remove_file(media.path).await;
+                        if !media.thumbnail_path.is_empty() {
+                            let _ = tokio::fs::remove_file(media.thumbnail_path).await;
+                        }
+                    }
+                    code = 200;
+                    data = String::from("Message deleted");
+                }
+                Ok(None) => {
+                    code = 403;
+                    data = String::from("Only your messages of the last 15 minutes can be deleted");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_message_requests(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_message_requests(id).await;
+            match sql_res {
+                Ok(threads) => {
+                    code = 200;
+                    data = serde_json::to_string(&threads).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_accept_thread(thread_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_accept_thread(id, thread_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Message request accepted");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Conversation not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_hide_thread(thread_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_hide_thread(id, thread_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Conversation hidden");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Conversation not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_mark_thread_read(thread_id: i64, id: String, input: MarkAsReadInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_mark_thread_read(id, thread_id, input).await;
+            match sql_res {
+                Ok(Some(last_read)) => {
+                    code = 200;
+                    data = serde_json::to_string(&last_read).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Thread not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_create_thread(id: String, input: NewThreadInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_create_thread(id, input).await;
+            match sql_res {
+                Ok(Some(thread_id)) => {
+                    code = 200;
+                    data = serde_json::to_string(&thread_id).unwrap();
+                }
+                Ok(None) => {
+                    code = 400;
+                    data = String::from("A group chat needs other participants");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_thread_messages(thread_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_thread_messages(id, thread_id).await;
+            match sql_res {
+                Ok(Some(messages)) => {
+                    code = 200;
+                    data = serde_json::to_string(&messages).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Thread not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_add_participant(thread_id: i64, id: String, input: AddParticipantInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_participant(id, thread_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Participant added");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Group chat not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_leave_thread(thread_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_leave_thread(id, thread_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Left the group chat");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Group chat not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_unread_count(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_unread_count(id).await;
+            match sql_res {
+                Ok(unread) => {
+                    code = 200;
+                    data = serde_json::to_string(&unread).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_ws(id: String, ws: warp::ws::Ws, db: Database, notifier: Notifier) -> Result<Box<dyn warp::Reply>, Infallible> {
+    match id.parse::<i64>() {
+        Err(_) => {
+            let data = String::from("Bad token format");
+            Ok(Box::new(warp::reply::json(&Response { code: 403, data })))
+        }
+        Ok(id) => Ok(Box::new(ws.on_upgrade(move |socket| handle_ws_connection(socket, id, db, notifier)))),
+    }
+}
+
+// push the events of the user to the socket and relay the events the user sends
+async fn handle_ws_connection(socket: warp::ws::WebSocket, id: i64, db: Database, notifier: Notifier) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let (connection, mut events) = notifier.connect(id);
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let text = match serde_json::to_string(&event) {
+                Ok(text) => text,
+                Err(e) => {
+                    println!("Failed to serialize event for user {}: {}", id, e);
+                    continue;
+                }
+            };
+            if ws_tx.send(warp::ws::Message::text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(result) = ws_rx.next().await {
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(_) => break,
+        };
+        if msg.is_close() {
+            break;
+        }
+        let sql_res = match msg.to_str().map(serde_json::from_str::<ClientEvent>) {
+            Ok(Ok(ClientEvent::Typing { thread_id })) => notify_typing(&db, id, thread_id).await,
+            // the read receipt is sent once the messages are marked as read
+            Ok(Ok(ClientEvent::Read { thread_id, message_id })) => {
+                let input = MarkAsReadInput { message_id: Some(message_id) };
+                db.db_mark_thread_read(id, thread_id, input).await.map(|_| ())
+            }
+            _ => continue,
+        };
+        if let Err(e) = sql_res {
+            println!("Failed to handle event of user {}: {}", id, e);
+        }
+    }
+    // dropping the events sender ends the push task
+    notifier.disconnect(id, connection);
+}
+
+// tell the other participants of the thread that the user is typing
+async fn notify_typing(db: &Database, id: i64, thread_id: i64) -> Result<(), sqlx::Error> {
+    let mut participants = db.db_get_thread_participants(thread_id).await?;
+    if !participants.contains(&id) {
+        return Ok(())
+    }
+    participants.retain(|participant| *participant != id);
+    db.db_notify(participants, ServerEvent::Typing { sender: id, thread_id }).await?;
+    Ok(())
+}
+
+pub async fn handle_buy_products(id: String, buy_products: BuyProducts, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_buy_products(id,buy_products).await;
+            match sql_res {
+                Ok(res) => {
+                    code = 200;
+                    data = serde_json::to_string(&res).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_add_discount_code(id: String, input: DiscountCodeInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    let valid = !input.code.trim().is_empty() && match (input.percent_off, input.amount_off) {
+        (Some(percent), None) => percent > 0 && percent <= 100,
+        (None, Some(amount)) => amount.0 > 0,
+        _ => false,
+    };
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if !valid => {
+            code = 400;
+            data = String::from("A discount code needs a code and either percent_off between 1 and 100 or a positive amount_off")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_discount_code(id, input).await;
+            match sql_res {
+                Ok(Some(discount)) => {
+                    code = 200;
+                    data = serde_json::to_string(&discount).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_my_discount_codes(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_my_discount_codes(id).await;
+            match sql_res {
+                Ok(discounts) => {
+                    code = 200;
+                    data = serde_json::to_string(&discounts).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_delete_discount_code(discount_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_delete_discount_code(id, discount_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Discount code deleted");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Discount code not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_add_sale_price(product_id: i64, id: String, input: SalePriceInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.price.is_negative() || input.starts_at >= input.ends_at => {
+            code = 400;
+            data = String::from("A sale needs a positive price and to start before it ends")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_sale_price(id, product_id, input).await;
+            match sql_res {
+                Ok(Some(sale)) => {
+                    code = 200;
+                    data = serde_json::to_string(&sale).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_sale_prices(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_sale_prices(product_id).await;
+            match sql_res {
+                Ok(sales) => {
+                    code = 200;
+                    data = serde_json::to_string(&sales).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_delete_sale_price(sale_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_delete_sale_price(id, sale_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Sale deleted");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Sale not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_create_auction(product_id: i64, id: String, input: AuctionInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    let reserve_price = input.reserve_price.unwrap_or(input.starting_price);
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.starting_price.is_negative() || reserve_price < input.starting_price
+            || input.extension_secs.map_or(false, |secs| secs < 0) => {
+            code = 400;
+            data = String::from("An auction needs a positive starting price under its reserve price")
+        }
+        Ok(id) => {
+            let sql_res = db.db_create_auction(id, product_id, input).await;
+            match sql_res {
+                Ok(Some(auction)) => {
+                    code = 200;
+                    data = serde_json::to_string(&auction).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Product not found or already under auction");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_auction(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_auction(product_id).await;
+            match sql_res {
+                Ok(Some(auction)) => {
+                    code = 200;
+                    data = serde_json::to_string(&auction).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Auction not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_bids(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_bids(product_id).await;
+            match sql_res {
+                Ok(bids) => {
+                    code = 200;
+                    data = serde_json::to_string(&bids).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_place_bid(product_id: i64, id: String, input: BidInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_place_bid(id, product_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Bid placed");
+                }
+                Ok(false) => {
+                    code = 400;
+                    data = String::from("Bid refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_make_offer(product_id: i64, id: String, input: OfferInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.price.is_negative() => {
+            code = 400;
+            data = String::from("An offer needs a positive price")
+        }
+        Ok(id) => {
+            let sql_res = db.db_make_offer(id, product_id, input).await;
+            match sql_res {
+                Ok(Some(offer)) => {
+                    code = 200;
+                    data = serde_json::to_string(&offer).unwrap();
+                }
+                Ok(None) => {
+                    code = 400;
+                    data = String::from("Offer refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_my_offers(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_my_offers(id).await;
+            match sql_res {
+                Ok(offers) => {
+                    code = 200;
+                    data = serde_json::to_string(&offers).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_accept_offer(offer_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_accept_offer(id, offer_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Offer accepted");
+                }
+                Ok(false) => {
+                    code = 400;
+                    data = String::from("Offer can't be accepted");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_decline_offer(offer_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_decline_offer(id, offer_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Offer declined");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Offer not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_counter_offer(offer_id: i64, id: String, input: CounterOfferInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.price.is_negative() => {
+            code = 400;
+            data = String::from("An offer needs a positive price")
+        }
+        Ok(id) => {
+            let sql_res = db.db_counter_offer(id, offer_id, input).await;
+            match sql_res {
+                Ok(Some(offer)) => {
+                    code = 200;
+                    data = serde_json::to_string(&offer).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Offer not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_set_subscription_tier(id: String, input: SubscriptionTierInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.price.0 <= 0 => {
+            code = 400;
+            data = String::from("A subscription needs a positive price")
+        }
+        Ok(id) => {
+            let sql_res = db.db_set_subscription_tier(id, input).await;
+            match sql_res {
+                Ok(tier) => {
+                    code = 200;
+                    data = serde_json::to_string(&tier).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_subscription_tier(user_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) => {
+            let sql_res = db.db_get_subscription_tier(user_id).await;
+            match sql_res {
+                Ok(Some(tier)) => {
+                    code = 200;
+                    data = serde_json::to_string(&tier).unwrap();
+                }
+                Ok(None) => {
+                    code = 404;
+                    data = String::from("Subscription tier not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_delete_subscription_tier(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_delete_subscription_tier(id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Subscription tier deleted");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Subscription tier not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_subscribe(user_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_subscribe(id, user_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Subscribed");
+                }
+                Ok(false) => {
+                    code = 400;
+                    data = String::from("Subscription refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_unsubscribe(user_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_unsubscribe(id, user_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Unsubscribed");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Subscription not found");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_my_subscriptions(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_my_subscriptions(id).await;
+            match sql_res {
+                Ok(subscriptions) => {
+                    code = 200;
+                    data = serde_json::to_string(&subscriptions).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_product_medias(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_product_medias(id, product_id).await;
+            match sql_res {
+                Ok(Some(medias)) => {
+                    code = 200;
+                    data = serde_json::to_string(&medias).unwrap();
+                }
+                Ok(None) => {
+                    code = 403;
+                    data = String::from("Buy the product or subscribe to its seller to access it");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_tip_user(user_id: i64, id: String, input: TipInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.amount.0 <= 0 => {
+            code = 400;
+            data = String::from("A tip needs a positive amount")
+        }
+        Ok(id) => {
+            let sql_res = db.db_tip_user(id, user_id, input).await;
+            match sql_res {
+                Ok(Some(tip)) => {
+                    code = 200;
+                    data = serde_json::to_string(&tip).unwrap();
+                }
+                Ok(None) => {
+                    code = 400;
+                    data = String::from("Tip refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_tip_product(product_id: i64, id: String, input: TipInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if input.amount.0 <= 0 => {
+            code = 400;
+            data = String::from("A tip needs a positive amount")
+        }
+        Ok(id) => {
+            let sql_res = db.db_tip_product(id, product_id, input).await;
+            match sql_res {
+                Ok(Some(tip)) => {
+                    code = 200;
+                    data = serde_json::to_string(&tip).unwrap();
+                }
+                Ok(None) => {
+                    code = 400;
+                    data = String::from("Tip refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_received_tips(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_received_tips(id).await;
+            match sql_res {
+                Ok(tips) => {
+                    code = 200;
+                    data = serde_json::to_string(&tips).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_add_to_cart(id: String, input: AddToCartInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_add_to_cart(id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Added to cart");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Product not available");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_remove_from_cart(product_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_remove_from_cart(id, product_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Removed from cart");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("Product not in cart");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_cart(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_cart(id).await;
+            match sql_res {
+                Ok(items) => {
+                    code = 200;
+                    data = serde_json::to_string(&items).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_checkout_cart(id: String, input: CheckoutInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_checkout_cart(id, input).await;
+            match sql_res {
+                Ok(CheckoutStatus::Done) => {
+                    code = 200;
+                    data = String::from("Cart bought");
+                }
+                Ok(CheckoutStatus::CartChanged) => {
+                    code = 409;
+                    data = String::from("Cart changed, review it before checkout");
+                }
+                Ok(CheckoutStatus::Refused) => {
+                    code = 403;
+                    data = String::from("Checkout refused");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_sales_stats(id: String, query: SalesStatsQuery, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    let bucket = query.bucket.unwrap_or_else(|| String::from("day"));
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(_) if bucket != "day" && bucket != "week" && bucket != "month" => {
+            code = 400;
+            data = String::from("Unknown bucket, expected day, week or month")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_sales_stats(id, bucket).await;
+            match sql_res {
+                Ok(stats) => {
+                    code = 200;
+                    data = serde_json::to_string(&stats).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_my_orders(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_my_orders(id).await;
+            match sql_res {
+                Ok(orders) => {
+                    code = 200;
+                    data = serde_json::to_string(&orders).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_ship_order(order_id: i64, id: String, input: ShipOrderInput, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_ship_order(id, order_id, input).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Order shipped");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("No paid order to ship");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_confirm_order(order_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_confirm_order(id, order_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Order delivered");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("No order to confirm");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_dispute_order(order_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_dispute_order(id, order_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Order disputed");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("No order to dispute");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_refund_order(order_id: i64, id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_refund_order(id, order_id).await;
+            match sql_res {
+                Ok(true) => {
+                    code = 200;
+                    data = String::from("Order refunded");
+                }
+                Ok(false) => {
+                    code = 404;
+                    data = String::from("No order to refund");
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
+    Ok(warp::reply::json(&Response { code, data }))
+}
+
+pub async fn handle_get_balance_history(id: String, db: Database) -> Result<impl warp::Reply, Infallible> {
+    let code;
+    let data;
+    match id.parse::<i64>() {
+        Err(_) => {
+            code = 403;
+            data = String::from("Bad token format")
+        }
+        Ok(id) => {
+            let sql_res = db.db_get_balance_history(id).await;
+            match sql_res {
+                Ok(entries) => {
+                    code = 200;
+                    data = serde_json::to_string(&entries).unwrap();
+                }
+                Err(e) => {
+                    code = 403;
+                    data = format!("{}", e);
+                }
+            }
+        }
+    }
     Ok(warp::reply::json(&Response { code, data }))
 }
 
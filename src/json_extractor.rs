@@ -13,10 +13,86 @@ pub fn json_body_send_messages() -> impl Filter<Extract= (SendMessageInput,), Er
     warp::body::json()
 }
 
+pub fn json_body_mark_as_read() -> impl Filter<Extract= (MarkAsReadInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_new_thread() -> impl Filter<Extract= (NewThreadInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_add_participant() -> impl Filter<Extract= (AddParticipantInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_edit_message() -> impl Filter<Extract= (EditMessageInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
 pub fn json_body_pattern() -> impl Filter<Extract= (Pattern,), Error = warp::Rejection> + Clone {
     warp::body::json()
 }
 
 pub fn json_body_buy_products() -> impl Filter<Extract= (BuyProducts,), Error = warp::Rejection> + Clone {
     warp::body::json()
-}
\ No newline at end of file
+}
+
+pub fn json_body_comment() -> impl Filter<Extract= (CommentInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_edit_comment() -> impl Filter<Extract= (EditCommentInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_edit_product() -> impl Filter<Extract= (EditProductInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_ship_order() -> impl Filter<Extract= (ShipOrderInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_add_to_cart() -> impl Filter<Extract= (AddToCartInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_checkout() -> impl Filter<Extract= (CheckoutInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_splits() -> impl Filter<Extract= (SplitsInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_discount_code() -> impl Filter<Extract= (DiscountCodeInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_sale_price() -> impl Filter<Extract= (SalePriceInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_auction() -> impl Filter<Extract= (AuctionInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_bid() -> impl Filter<Extract= (BidInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_offer() -> impl Filter<Extract= (OfferInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_counter_offer() -> impl Filter<Extract= (CounterOfferInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_subscription_tier() -> impl Filter<Extract= (SubscriptionTierInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
+
+pub fn json_body_tip() -> impl Filter<Extract= (TipInput,), Error = warp::Rejection> + Clone {
+    warp::body::json()
+}
@@ -2,5 +2,8 @@ pub mod database;
 pub mod filters;
 mod handlers;
 pub mod models;
+pub mod scheduler;
+pub mod view_counter;
+pub mod notifier;
 mod json_extractor;
 mod ffmpeg_utils;
\ No newline at end of file
@@ -7,6 +7,158 @@ use warp::{
 };
 
 use chrono::{DateTime, Utc, TimeZone};
+use std::fmt;
+use std::str::FromStr;
+
+/// Quantity of quadreum, counted in the smallest unit of the token.
+/// Serialized as a decimal string, "12.50" for Amount(1250), so javascript clients don't lose precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Amount(pub i64);
+
+impl Amount {
+    /// Decimals of the quadreum token
+    pub const DECIMALS: u32 = 2;
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Share of the amount in basis points (1/100 of a percent), rounded toward zero
+    pub fn checked_bps(self, bps: i64) -> Option<Amount> {
+        let share = self.0 as i128 * bps as i128 / 10000;
+        if share > i64::MAX as i128 || share < i64::MIN as i128 {
+            None
+        } else {
+            Some(Amount(share as i64))
+        }
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = 10i128.pow(Amount::DECIMALS);
+        let value = self.0 as i128;
+        let sign = if value < 0 { "-" } else { "" };
+        let value = value.abs();
+        write!(f, "{}{}.{:0width$}", sign, value / unit, value % unit, width = Amount::DECIMALS as usize)
+    }
+}
+
+#[derive(Debug)]
+pub struct AmountError;
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid amount, expected a number with at most {} decimals", Amount::DECIMALS)
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let mut parts = digits.splitn(2, '.');
+        let whole = parts.next().unwrap_or("");
+        let fraction = parts.next().unwrap_or("");
+        if (whole.is_empty() && fraction.is_empty())
+            || fraction.len() > Amount::DECIMALS as usize
+            || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(AmountError);
+        }
+        let whole = if whole.is_empty() { 0 } else { whole.parse::<i64>().map_err(|_| AmountError)? };
+        let fraction = format!("{:0<width$}", fraction, width = Amount::DECIMALS as usize)
+            .parse::<i64>()
+            .map_err(|_| AmountError)?;
+        let value = whole
+            .checked_mul(10i64.pow(Amount::DECIMALS))
+            .and_then(|value| value.checked_add(fraction))
+            .ok_or(AmountError)?;
+        Ok(Amount(if negative { -value } else { value }))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// JSON numbers are accepted too, through their decimal representation
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> serde::de::Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an amount as a string or a number with at most {} decimals", Amount::DECIMALS)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Amount, E> {
+        value.parse::<Amount>().map_err(E::custom)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+}
+
+/// Serialize a quadreum column read as i64 like an Amount
+pub mod as_amount {
+    use super::Amount;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        Amount(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        Ok(Amount::deserialize(deserializer)?.0)
+    }
+}
+
+/// Same as as_amount for a nullable column
+pub mod as_option_amount {
+    use super::Amount;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<i64>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(Amount).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+        Ok(Option::<Amount>::deserialize(deserializer)?.map(|amount| amount.0))
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct User {
@@ -14,9 +166,16 @@ pub struct User {
     pub username: String,
     pub eth_address: String,
     pub bio : String,
+    #[serde(with = "as_amount")]
     pub quadreum: i64,
     pub avatar: String,
     pub created_at: DateTime<Utc>,
+    // quadreum of the user's sales held in escrow
+    #[serde(with = "as_amount")]
+    pub quadreum_escrow: i64,
+    // quadreum held for the user's highest bids
+    #[serde(with = "as_amount")]
+    pub quadreum_held: i64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -25,6 +184,7 @@ pub struct UserProfile {
     pub username: String,
     pub eth_address: String,
     pub bio: String,
+    #[serde(with = "as_amount")]
     pub quadreum: i64,
     pub avatar: String,
     pub followers: i64,
@@ -43,9 +203,36 @@ pub struct AllMessagesInput {
     pub user2: i64,
 }
 
+// the message goes to thread_id, or to the direct thread with receiver
+// with product_id, the message shares the product
 #[derive(Deserialize)]
 pub struct SendMessageInput {
-    pub receiver: i64,
+    pub receiver: Option<i64>,
+    pub thread_id: Option<i64>,
+    pub content: String,
+    pub product_id: Option<i64>,
+}
+
+// read the messages of the thread up to message_id, or all of them
+#[derive(Deserialize)]
+pub struct MarkAsReadInput {
+    pub message_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct NewThreadInput {
+    pub title: Option<String>,
+    // the other participants of the group chat
+    pub participants: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct AddParticipantInput {
+    pub user_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct EditMessageInput {
     pub content: String,
 }
 
@@ -66,16 +253,25 @@ pub struct Product {
     pub id: i64,
     pub product_type: String,
     pub seller_id: i64,
-    pub buyers_id: i64,
     pub description: String,
+    #[serde(with = "as_amount")]
     pub price: i64,
+    // units left of a REAL product, None for a MEDIA product
+    pub stock: Option<i64>,
     pub media_id: i64,
     pub views: i64,
     pub likes: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 
 }
 
+#[derive(Deserialize)]
+pub struct EditProductInput {
+    pub description: Option<String>,
+    pub price: Option<Amount>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Media {
     pub id: i64,
@@ -86,9 +282,390 @@ pub struct Media {
 
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NewMedia {
+    pub path: String,
+    pub thumbnail_path: String,
+    pub media_type: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BuyProducts {
-    pub products: Vec<i64>
+    pub products: Vec<i64>,
+    // required when buying REAL products
+    pub shipping_address: Option<String>,
+    pub discount_code: Option<String>,
+}
+
+// Discount given by a seller on their products, or on one of them when product_id is set
+// exactly one of percent_off and amount_off is set
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DiscountCode {
+    pub id: i64,
+    pub seller_id: i64,
+    pub code: String,
+    pub percent_off: Option<i64>,
+    #[serde(with = "as_option_amount")]
+    pub amount_off: Option<i64>,
+    pub max_uses: Option<i64>,
+    pub uses: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub product_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DiscountCode {
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.map_or(true, |expires_at| now < expires_at)
+            && self.max_uses.map_or(true, |max_uses| self.uses < max_uses)
+    }
+
+    pub fn applies_to(&self, product: &Product) -> bool {
+        product.seller_id == self.seller_id && self.product_id.map_or(true, |id| id == product.id)
+    }
+
+    /// Take the discount off the prices of an order, a price never goes below zero.
+    /// percent_off is taken off each price, amount_off once off the whole order starting with the first price.
+    /// Returns the discount taken, None if it overflows.
+    pub fn apply(&self, prices: &mut [&mut i64]) -> Option<Amount> {
+        let mut taken = Amount::ZERO;
+        match (self.percent_off, self.amount_off) {
+            (Some(percent), _) => {
+                let bps = percent.checked_mul(100)?;
+                for price in prices.iter_mut() {
+                    let off = Amount(**price).checked_bps(bps)?.0.max(0).min(**price);
+                    **price -= off;
+                    taken = taken.checked_add(Amount(off))?;
+                }
+            }
+            (None, Some(amount)) => {
+                let mut left = amount.max(0);
+                for price in prices.iter_mut() {
+                    let off = left.min(**price).max(0);
+                    **price -= off;
+                    left -= off;
+                    taken = taken.checked_add(Amount(off))?;
+                }
+            }
+            (None, None) => (),
+        }
+        Some(taken)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DiscountCodeInput {
+    pub code: String,
+    pub percent_off: Option<i64>,
+    pub amount_off: Option<Amount>,
+    pub max_uses: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub product_id: Option<i64>,
+}
+
+// Price of a product between starts_at and ends_at
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SalePrice {
+    pub id: i64,
+    pub product_id: i64,
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct SalePriceInput {
+    pub price: Amount,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+// Auction of a product, status is OPEN, SOLD or UNSOLD
+// a bid in the last extension_secs pushes ends_at back by extension_secs
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Auction {
+    pub id: i64,
+    pub product_id: i64,
+    #[serde(with = "as_amount")]
+    pub starting_price: i64,
+    #[serde(with = "as_amount")]
+    pub reserve_price: i64,
+    pub ends_at: DateTime<Utc>,
+    pub extension_secs: i64,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct AuctionInput {
+    pub starting_price: Amount,
+    pub reserve_price: Option<Amount>,
+    pub ends_at: DateTime<Utc>,
+    pub extension_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Bid {
+    pub id: i64,
+    pub auction_id: i64,
+    pub bidder_id: i64,
+    #[serde(with = "as_amount")]
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct BidInput {
+    pub amount: Amount,
+    // required to bid on a REAL product
+    pub shipping_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuctionDetail {
+    #[serde(flatten)]
+    pub auction: Auction,
+    pub highest_bid: Option<Bid>,
+}
+
+// Price proposed for a product, status is PENDING, ACCEPTED, DECLINED, COUNTERED or EXPIRED
+// the user who did not propose it answers it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Offer {
+    pub id: i64,
+    pub product_id: i64,
+    pub buyer_id: i64,
+    pub seller_id: i64,
+    pub proposed_by: i64,
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub status: String,
+    pub parent_id: Option<i64>,
+    pub shipping_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Offer {
+    pub fn recipient(&self) -> i64 {
+        if self.proposed_by == self.buyer_id { self.seller_id } else { self.buyer_id }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OfferInput {
+    pub price: Amount,
+    // required to make an offer on a REAL product
+    pub shipping_address: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CounterOfferInput {
+    pub price: Amount,
+}
+
+// Monthly price to subscribe to a creator
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SubscriptionTier {
+    pub creator_id: i64,
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct SubscriptionTierInput {
+    pub price: Amount,
+}
+
+// status is ACTIVE, PAST_DUE (renewal failed, access kept until grace_until),
+// CANCELLED (access kept until current_period_end) or EXPIRED
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Subscription {
+    pub subscriber_id: i64,
+    pub creator_id: i64,
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub status: String,
+    pub current_period_end: DateTime<Utc>,
+    pub grace_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Quadreum given to a creator, product_id is set for a tip on a product
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Tip {
+    pub id: i64,
+    pub sender_id: i64,
+    pub recipient_id: i64,
+    pub product_id: Option<i64>,
+    #[serde(with = "as_amount")]
+    pub amount: i64,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct TipInput {
+    pub amount: Amount,
+    pub message: Option<String>,
+    // also post the tip in the direct messages, false by default
+    pub post_in_thread: Option<bool>,
+}
+
+// Share of the seller's part of each sale, after the platform fee
+// kind is REFERRAL or CO_CREATOR, bps is in basis points (1/100 of a percent)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Split {
+    pub user_id: i64,
+    pub bps: i64,
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+pub struct SplitsInput {
+    pub splits: Vec<Split>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CartItem {
+    pub product_id: i64,
+    pub seller_id: i64,
+    pub username: String,
+    pub product_type: String,
+    pub description: String,
+    // price when added to the cart
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub quantity: i64,
+    pub stock: Option<i64>,
+    pub path: String,
+    pub thumbnail_path: String,
+    pub media_type: String,
+    pub added_at: DateTime<Utc>,
+    pub entitled: bool,
+}
+
+impl CartItem {
+    /// Same as Feed::hide_locked_media
+    pub fn hide_locked_media(&mut self) {
+        if !self.entitled {
+            self.path = String::new();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AddToCartInput {
+    pub product_id: i64,
+    // ignored for MEDIA products
+    pub quantity: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CheckoutInput {
+    // required when the cart holds REAL products
+    pub shipping_address: Option<String>,
+    pub discount_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub enum CheckoutStatus {
+    Done,
+    // prices changed or sold out products were removed, the buyer has to review the cart
+    CartChanged,
+    Refused,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SalesStatsQuery {
+    // day, week or month, defaults to day
+    pub bucket: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SalesStats {
+    pub revenue: Vec<RevenueBucket>,
+    pub best_sellers: Vec<ProductSales>,
+    pub views: i64,
+    pub sales: i64,
+    // sales per view over all the seller's products
+    pub conversion: f64,
+    pub follower_growth: Vec<FollowerBucket>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RevenueBucket {
+    pub period: DateTime<Utc>,
+    #[serde(with = "as_amount")]
+    pub revenue: i64,
+    pub sales: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProductSales {
+    pub product_id: i64,
+    pub description: String,
+    pub views: i64,
+    pub sales: i64,
+    #[serde(with = "as_amount")]
+    pub revenue: i64,
+    pub conversion: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FollowerBucket {
+    pub period: DateTime<Utc>,
+    pub followers: i64,
+}
+
+// Purchase of a REAL product
+// status goes PAID -> SHIPPED -> DELIVERED, or to DISPUTED before delivery, or REFUNDED by the seller
+// escrow goes HELD -> RELEASED to the seller, FROZEN by a dispute, or REFUNDED to the buyer
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Order {
+    pub id: i64,
+    pub product_id: i64,
+    pub buyer_id: i64,
+    pub seller_id: i64,
+    pub purchase_id: Option<i64>,
+    #[serde(with = "as_amount")]
+    pub price: i64,
+    pub shipping_address: String,
+    pub status: String,
+    pub tracking_number: Option<String>,
+    pub escrow: String,
+    pub release_after: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// A quadreum movement on a user balance
+// amount is negative when spent
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BalanceEntry {
+    pub id: i64,
+    pub user_id: i64,
+    #[serde(with = "as_amount")]
+    pub amount: i64,
+    pub reason: String,
+    pub product_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ShipOrderInput {
+    pub tracking_number: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FeedQuery {
+    // trending, new or top, defaults to new
+    pub sort: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -101,32 +678,207 @@ pub struct Feed {
     pub avatar: String,
     pub product_type: String,
     pub description: String,
+    #[serde(with = "as_amount")]
     pub price: i64,
+    // price of the running sale if any
+    #[serde(with = "as_option_amount")]
+    pub sale_price: Option<i64>,
+    pub stock: Option<i64>,
     pub views: i64,
     pub likes: i64,
+    // the cover of the product
     pub path: String,
     pub thumbnail_path: String,
     pub media_type: String,
     pub created_at: DateTime<Utc>,
+    // every media of the product, cover included, in display order
+    pub medias: serde_json::Value,
+    pub liked_by_me: bool,
+    pub comment_count: i64,
+    // the user owns, sells or subscribes to the seller of the product
+    pub entitled: bool,
+}
+
+impl Feed {
+    /// Keep only the thumbnails of a content the user is not entitled to
+    pub fn hide_locked_media(&mut self) {
+        if self.entitled {
+            return
+        }
+        self.path = String::new();
+        if let serde_json::Value::Array(medias) = &mut self.medias {
+            for media in medias.iter_mut().filter_map(|media| media.as_object_mut()) {
+                media.remove("path");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProductDetail {
+    #[serde(flatten)]
+    pub product: Feed,
+    pub purchased_by_me: bool,
+    // other products on sale from the same seller
+    pub related: Vec<Feed>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Comment {
+    pub id: i64,
+    pub product_id: i64,
+    pub author_id: i64,
+    pub username: String,
+    pub avatar: String,
+    pub parent_id: Option<i64>,
+    pub content: String,
+    pub reply_count: i64,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct CommentInput {
+    pub content: String,
+    // reply to this comment
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct EditCommentInput {
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentsQuery {
+    // list the replies of this comment instead of the top level comments
+    pub parent_id: Option<i64>,
+    // id of the last comment of the previous page
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
 }
 
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Message {
     pub id: i64,
+    pub thread_id: i64,
+    pub sender: i64,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub read_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub body: MessageBody,
+}
+
+// content is the text of the message, or the caption of its attachment
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MessageBody {
+    Text { content: String },
+    Media { content: String, media: Media },
+    // product is None once the product is deleted
+    Product { content: String, product: Option<ProductCard> },
+    // tombstone of a message deleted by its sender
+    Deleted,
+}
+
+// Product shared in a message
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProductCard {
+    pub id: i64,
+    pub seller_id: i64,
+    pub description: String,
+    pub product_type: String,
+    // read from the json built by the database
+    #[serde(serialize_with = "as_amount::serialize")]
+    pub price: i64,
+    pub cover_path: String,
+}
+
+// Row of the message_details view
+#[derive(Debug, Clone)]
+pub struct MessageRow {
+    pub id: i64,
+    pub thread_id: i64,
     pub sender: i64,
-    pub receiver: i64,
     pub content: String,
-    pub created_at: DateTime<Utc>
+    pub created_at: DateTime<Utc>,
+    pub kind: String,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub media: Option<serde_json::Value>,
+    pub product: Option<serde_json::Value>,
+}
+
+impl From<MessageRow> for Message {
+    fn from(row: MessageRow) -> Self {
+        let content = row.content;
+        let media = row.media.and_then(|media| serde_json::from_value(media).ok());
+        let body = match (row.kind.as_str(), media) {
+            ("MEDIA", Some(media)) => MessageBody::Media { content, media },
+            ("PRODUCT", _) => {
+                let product = row.product.and_then(|product| serde_json::from_value(product).ok());
+                MessageBody::Product { content, product }
+            }
+            ("DELETED", _) => MessageBody::Deleted,
+            _ => MessageBody::Text { content },
+        };
+        Message {
+            id: row.id,
+            thread_id: row.thread_id,
+            sender: row.sender,
+            created_at: row.created_at,
+            edited_at: row.edited_at,
+            read_at: row.read_at,
+            body,
+        }
+    }
+}
+
+// Event pushed to the users connected to /ws
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Message { message: Message },
+    Typing { sender: i64, thread_id: i64 },
+    Read { sender: i64, thread_id: i64, message_id: i64 },
+}
+
+// Event sent by a user connected to /ws to the other participants of a thread
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientEvent {
+    Typing { thread_id: i64 },
+    Read { thread_id: i64, message_id: i64 },
+}
+
+// Payload of a notification on the events channel
+// a message only comes with its id, the listener loads it since a payload can't reach 8000 bytes
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum EventNotification {
+    Event { user_ids: Vec<i64>, event: ServerEvent },
+    Message { message_id: i64 },
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Thread {
     pub id: i64,
-    pub username: String,
-    pub avatar: String,
+    pub title: Option<String>,
+    pub is_group: bool,
+    // id, username and avatar of every participant
+    pub participants: serde_json::Value,
+    // the last message, empty in a thread without messages
     pub content: String,
-    pub created_at: DateTime<Utc>
+    pub created_at: DateTime<Utc>,
+    // messages of the other participants not read yet
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UnreadCount {
+    pub unread_count: i64,
 }
 
 
@@ -209,9 +961,14 @@ pub enum PartType {
     SellerId(i64),
     Description(String),
     ProductType(String),
-    Price(i64),
+    // None when the price can't be parsed
+    Price(Option<Amount>),
+    // None when the stock can't be parsed or is negative
+    Stock(Option<i64>),
     FilePart(Part),
     MediaType(String),
+    // None when the cover can't be parsed
+    Cover(Option<usize>),
     NoFormData,
 }
 
@@ -226,9 +983,14 @@ pub struct ResultData {
     pub seller_id: i64,
     pub description: String,
     pub product_type: String,
-    pub price: i64,
-    pub file_part: Option<Part>,
-    pub media_type: String
+    pub price: Option<Amount>,
+    // None when no stock is given, Some(None) when it can't be parsed or is negative
+    pub stock: Option<Option<i64>>,
+    // the medias of the product, in display order
+    pub file_parts: Vec<Part>,
+    pub media_type: String,
+    // index of the cover in file_parts, None when it can't be parsed
+    pub cover: Option<usize>,
 }
 
 impl ResultData {
@@ -237,9 +999,40 @@ impl ResultData {
             seller_id: 0,
             description: "".to_string(),
             product_type: "".to_string(),
-            price: 0,
-            file_part: None,
+            price: Some(Amount::ZERO),
+            stock: None,
+            file_parts: Vec::new(),
             media_type: "".to_string(),
+            cover: Some(0),
+        }
+    }
+}
+
+pub enum MessagePartType {
+    ThreadId(i64),
+    Receiver(i64),
+    Caption(String),
+    FilePart(Part),
+    NoFormData,
+}
+
+// Message with an attachment
+pub struct MessageData {
+    pub sender: Option<i64>,
+    pub thread_id: Option<i64>,
+    pub receiver: Option<i64>,
+    pub caption: String,
+    pub file_part: Option<Part>,
+}
+
+impl MessageData {
+    pub fn new() -> Self {
+        MessageData {
+            sender: None,
+            thread_id: None,
+            receiver: None,
+            caption: "".to_string(),
+            file_part: None,
         }
     }
 }
@@ -258,4 +1051,152 @@ impl ProfileData {
             file_part: None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amount_parses_decimal_strings() {
+        assert_eq!("12.50".parse::<Amount>().unwrap(), Amount(1250));
+        assert_eq!("12.5".parse::<Amount>().unwrap(), Amount(1250));
+        assert_eq!("12".parse::<Amount>().unwrap(), Amount(1200));
+        assert_eq!("0.07".parse::<Amount>().unwrap(), Amount(7));
+        assert_eq!(".5".parse::<Amount>().unwrap(), Amount(50));
+        assert_eq!("3.".parse::<Amount>().unwrap(), Amount(300));
+        assert_eq!(" 1.00 ".parse::<Amount>().unwrap(), Amount(100));
+    }
+
+    #[test]
+    fn amount_parses_the_sign() {
+        assert_eq!("-12.50".parse::<Amount>().unwrap(), Amount(-1250));
+        assert_eq!("-0.01".parse::<Amount>().unwrap(), Amount(-1));
+        assert!("--1".parse::<Amount>().is_err());
+        assert!("+1".parse::<Amount>().is_err());
+        assert!("-".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn amount_rejects_malformed_strings() {
+        assert!("".parse::<Amount>().is_err());
+        assert!(".".parse::<Amount>().is_err());
+        assert!("1.234".parse::<Amount>().is_err());
+        assert!("1.2.3".parse::<Amount>().is_err());
+        assert!("1e3".parse::<Amount>().is_err());
+        assert!("abc".parse::<Amount>().is_err());
+        assert!("1,50".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn amount_rejects_overflow() {
+        assert_eq!("92233720368547758.07".parse::<Amount>().unwrap(), Amount(i64::MAX));
+        assert!("92233720368547758.08".parse::<Amount>().is_err());
+        assert!("99999999999999999999".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn amount_displays_with_two_decimals() {
+        assert_eq!(Amount(1250).to_string(), "12.50");
+        assert_eq!(Amount(7).to_string(), "0.07");
+        assert_eq!(Amount(0).to_string(), "0.00");
+        assert_eq!(Amount(-1).to_string(), "-0.01");
+        assert_eq!(Amount(-1250).to_string(), "-12.50");
+        assert_eq!(Amount(i64::MIN).to_string(), "-92233720368547758.08");
+    }
+
+    #[test]
+    fn amount_display_round_trips() {
+        for value in &[0, 1, -1, 99, 100, -12345, i64::MAX] {
+            let amount = Amount(*value);
+            assert_eq!(amount.to_string().parse::<Amount>().unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn amount_deserializes_strings_and_numbers() {
+        assert_eq!(serde_json::from_str::<Amount>(r#""12.50""#).unwrap(), Amount(1250));
+        assert_eq!(serde_json::from_str::<Amount>("12.5").unwrap(), Amount(1250));
+        assert_eq!(serde_json::from_str::<Amount>("12").unwrap(), Amount(1200));
+        assert_eq!(serde_json::from_str::<Amount>("-0.5").unwrap(), Amount(-50));
+    }
+
+    #[test]
+    fn amount_rejects_numbers_with_more_decimals() {
+        assert!(serde_json::from_str::<Amount>("0.125").is_err());
+        assert!(serde_json::from_str::<Amount>("true").is_err());
+    }
+
+    #[test]
+    fn checked_bps_rounds_toward_zero() {
+        assert_eq!(Amount(1000).checked_bps(250), Some(Amount(25)));
+        assert_eq!(Amount(999).checked_bps(250), Some(Amount(24)));
+        assert_eq!(Amount(-999).checked_bps(250), Some(Amount(-24)));
+        assert_eq!(Amount(1).checked_bps(9999), Some(Amount(0)));
+        assert_eq!(Amount(1234).checked_bps(10000), Some(Amount(1234)));
+        assert_eq!(Amount(1234).checked_bps(0), Some(Amount::ZERO));
+    }
+
+    #[test]
+    fn checked_bps_handles_large_amounts() {
+        // the product is computed on i128, it can't overflow before the division
+        assert_eq!(Amount(i64::MAX).checked_bps(10000), Some(Amount(i64::MAX)));
+        assert_eq!(Amount(i64::MAX).checked_bps(5000), Some(Amount(i64::MAX / 2)));
+        assert_eq!(Amount(i64::MAX).checked_bps(10001), None);
+        assert_eq!(Amount(i64::MIN).checked_bps(20000), None);
+    }
+
+    fn discount(percent_off: Option<i64>, amount_off: Option<i64>) -> DiscountCode {
+        DiscountCode {
+            id: 1,
+            seller_id: 1,
+            code: String::from("CODE"),
+            percent_off,
+            amount_off,
+            max_uses: None,
+            uses: 0,
+            expires_at: None,
+            product_id: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn percent_off_is_taken_off_each_price() {
+        let (mut first, mut second) = (1000, 999);
+        let taken = discount(Some(10), None).apply(&mut [&mut first, &mut second]);
+        assert_eq!(taken, Some(Amount(199)));
+        assert_eq!((first, second), (900, 900));
+    }
+
+    #[test]
+    fn amount_off_is_taken_once_per_order() {
+        let (mut first, mut second) = (300, 300);
+        let taken = discount(None, Some(500)).apply(&mut [&mut first, &mut second]);
+        assert_eq!(taken, Some(Amount(500)));
+        assert_eq!((first, second), (0, 100));
+
+        let (mut first, mut second) = (300, 300);
+        let taken = discount(None, Some(100)).apply(&mut [&mut first, &mut second]);
+        assert_eq!(taken, Some(Amount(100)));
+        assert_eq!((first, second), (200, 300));
+    }
+
+    #[test]
+    fn discount_never_goes_below_zero() {
+        let mut price = 300;
+        assert_eq!(discount(None, Some(1000)).apply(&mut [&mut price]), Some(Amount(300)));
+        assert_eq!(price, 0);
+        let mut price = 300;
+        assert_eq!(discount(Some(100), None).apply(&mut [&mut price]), Some(Amount(300)));
+        assert_eq!(price, 0);
+    }
+
+    #[test]
+    fn discount_overflow_is_reported() {
+        let mut price = 1000;
+        assert_eq!(discount(Some(i64::MAX), None).apply(&mut [&mut price]), None);
+        let (mut first, mut second) = (i64::MAX, i64::MAX);
+        assert_eq!(discount(Some(100), None).apply(&mut [&mut first, &mut second]), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::models::*;
+use crate::database::Database;
+
+use sqlx::postgres::PgListener;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+
+/// Postgres channel carrying the events of every server instance
+pub const EVENTS_CHANNEL: &str = "swell_events";
+
+/// WebSocket connections of the users to this server instance.
+/// A user connected several times gets every event on each connection.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    state: Arc<Mutex<NotifierState>>,
+}
+
+#[derive(Debug, Default)]
+struct NotifierState {
+    next_connection: u64,
+    // user id => connection id => events sender
+    clients: HashMap<i64, HashMap<u64, mpsc::UnboundedSender<ServerEvent>>>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Notifier {
+            state: Arc::new(Mutex::new(NotifierState::default())),
+        }
+    }
+
+    /// Register a connection of the user, returns its id and the events to push to it
+    pub fn connect(&self, user_id: i64) -> (u64, mpsc::UnboundedReceiver<ServerEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut state = self.state.lock().unwrap();
+        let connection = state.next_connection;
+        state.next_connection += 1;
+        state.clients.entry(user_id).or_insert_with(HashMap::new).insert(connection, sender);
+        (connection, receiver)
+    }
+
+    pub fn disconnect(&self, user_id: i64, connection: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(connections) = state.clients.get_mut(&user_id) {
+            connections.remove(&connection);
+            if connections.is_empty() {
+                state.clients.remove(&user_id);
+            }
+        }
+    }
+
+    /// Push the event to the connections of the user on this instance
+    pub fn send(&self, user_id: i64, event: &ServerEvent) {
+        let state = self.state.lock().unwrap();
+        if let Some(connections) = state.clients.get(&user_id) {
+            for sender in connections.values() {
+                // a closed connection is removed by its disconnect
+                let _ = sender.send(event.clone());
+            }
+        }
+    }
+}
+
+/// Forward the events notified on EVENTS_CHANNEL to the users connected to this instance,
+/// reconnecting to the database when the listener fails
+pub async fn listen_events(database_url: String, db: Database, notifier: Notifier) {
+    loop {
+        if let Err(e) = forward_events(&database_url, &db, &notifier).await {
+            println!("Events listener failed: {}", e);
+        }
+        time::delay_for(Duration::from_secs(5)).await;
+    }
+}
+
+async fn forward_events(database_url: &str, db: &Database, notifier: &Notifier) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect(database_url).await?;
+    listener.listen(EVENTS_CHANNEL).await?;
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str::<EventNotification>(notification.payload()) {
+            Ok(EventNotification::Event { user_ids, event }) => {
+                for user_id in user_ids {
+                    notifier.send(user_id, &event);
+                }
+            }
+            Ok(EventNotification::Message { message_id }) => {
+                if let Err(e) = forward_message(db, notifier, message_id).await {
+                    println!("Failed to forward message {}: {}", message_id, e);
+                }
+            }
+            Err(e) => println!("Bad event notification: {}", e),
+        }
+    }
+}
+
+// push the message to its thread participants
+async fn forward_message(db: &Database, notifier: &Notifier, message_id: i64) -> Result<(), sqlx::Error> {
+    let message = match db.db_get_message(message_id).await? {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let participants = db.db_get_thread_participants(message.thread_id).await?;
+    let event = ServerEvent::Message { message };
+    for user_id in participants {
+        notifier.send(user_id, &event);
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::database::*;
+use crate::view_counter::*;
+
+use std::time::Duration;
+use tokio::time;
+
+/// Periodically recompute the ranking scores used by the trending and top feeds
+pub async fn refresh_product_scores(db: Database, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = db.db_refresh_product_scores().await {
+            println!("Failed to refresh product scores: {}", e);
+        }
+    }
+}
+
+/// Periodically write the buffered product views to the products table
+pub async fn flush_product_views(db: Database, views: ViewCounter, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        for (product_id, count) in views.take_pending() {
+            if let Err(e) = db.db_add_product_views(product_id, count).await {
+                println!("Failed to flush views of product {}: {}", product_id, e);
+                views.restore(product_id, count);
+            }
+        }
+    }
+}
+
+/// Periodically settle the escrows of the orders past their deadline:
+/// released to the sellers once shipped, refunded to the buyers if never shipped or still disputed
+pub async fn release_due_escrows(db: Database, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        let orders = match db.db_get_due_escrows().await {
+            Ok(orders) => orders,
+            Err(e) => {
+                println!("Failed to get due escrows: {}", e);
+                continue;
+            }
+        };
+        for order in orders {
+            if order.status == "SHIPPED" {
+                if let Err(e) = db.db_auto_release_order(order.id).await {
+                    println!("Failed to release escrow of order {}: {}", order.id, e);
+                }
+            } else if let Err(e) = db.db_auto_refund_order(order.id).await {
+                println!("Failed to refund escrow of order {}: {}", order.id, e);
+            }
+        }
+    }
+}
+
+/// Periodically close the ended auctions and sell their products to the highest bidders
+pub async fn settle_auctions(db: Database, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        let auctions = match db.db_get_due_auctions().await {
+            Ok(auctions) => auctions,
+            Err(e) => {
+                println!("Failed to get due auctions: {}", e);
+                continue;
+            }
+        };
+        for auction in auctions {
+            if let Err(e) = db.db_settle_auction(auction.id).await {
+                println!("Failed to settle auction {}: {}", auction.id, e);
+            }
+        }
+    }
+}
+
+/// Periodically expire the offers left unanswered
+pub async fn expire_offers(db: Database, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = db.db_expire_offers().await {
+            println!("Failed to expire offers: {}", e);
+        }
+    }
+}
+
+/// Periodically debit the subscriptions reaching the end of their period
+pub async fn renew_subscriptions(db: Database, period: Duration) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        let subscriptions = match db.db_get_due_subscriptions().await {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                println!("Failed to get due subscriptions: {}", e);
+                continue;
+            }
+        };
+        for subscription in subscriptions {
+            if let Err(e) = db.db_renew_subscription(subscription.subscriber_id, subscription.creator_id).await {
+                println!("Failed to renew subscription of user {} to {}: {}", subscription.subscriber_id, subscription.creator_id, e);
+            }
+        }
+    }
+}
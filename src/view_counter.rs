@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// views remembered at most, the expired ones are forgotten first when it is reached
+const MAX_SEEN: usize = 100_000;
+
+/// In memory buffer of product views.
+/// A user viewing the same product several times within `window` counts once,
+/// and counted views are kept here until they are flushed to the products table.
+#[derive(Debug, Clone)]
+pub struct ViewCounter {
+    window: Duration,
+    max_seen: usize,
+    state: Arc<Mutex<ViewState>>,
+}
+
+#[derive(Debug, Default)]
+struct ViewState {
+    // (user id, product id) => last counted view
+    seen: HashMap<(i64, i64), Instant>,
+    // product id => views not flushed yet
+    pending: HashMap<i64, i64>,
+}
+
+impl ViewCounter {
+    pub fn new(window: Duration) -> Self {
+        ViewCounter {
+            window,
+            max_seen: MAX_SEEN,
+            state: Arc::new(Mutex::new(ViewState::default())),
+        }
+    }
+
+    /// Returns false if the view was already counted within the window,
+    /// or can't be told apart from one because too many views are remembered
+    pub fn record(&self, user_id: i64, product_id: i64) -> bool {
+        let now = Instant::now();
+        let window = self.window;
+        let mut state = self.state.lock().unwrap();
+        if let Some(last) = state.seen.get(&(user_id, product_id)) {
+            if now.duration_since(*last) < window {
+                return false;
+            }
+        }
+        if state.seen.len() >= self.max_seen {
+            state.seen.retain(|_, last| now.duration_since(*last) < window);
+            if state.seen.len() >= self.max_seen {
+                return false;
+            }
+        }
+        state.seen.insert((user_id, product_id), now);
+        *state.pending.entry(product_id).or_insert(0) += 1;
+        true
+    }
+
+    /// Take the views waiting to be flushed and forget the expired windows
+    pub fn take_pending(&self) -> HashMap<i64, i64> {
+        let now = Instant::now();
+        let window = self.window;
+        let mut state = self.state.lock().unwrap();
+        state.seen.retain(|_, last| now.duration_since(*last) < window);
+        std::mem::replace(&mut state.pending, HashMap::new())
+    }
+
+    /// Put back views that could not be flushed
+    pub fn restore(&self, product_id: i64, views: i64) {
+        let mut state = self.state.lock().unwrap();
+        *state.pending.entry(product_id).or_insert(0) += views;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeat_view_within_window_is_not_counted() {
+        let views = ViewCounter::new(Duration::from_secs(60));
+        assert!(views.record(1, 10));
+        assert!(!views.record(1, 10));
+        // another user or another product is a new view
+        assert!(views.record(2, 10));
+        assert!(views.record(1, 11));
+        let pending = views.take_pending();
+        assert_eq!(pending.get(&10), Some(&2));
+        assert_eq!(pending.get(&11), Some(&1));
+    }
+
+    #[test]
+    fn view_after_window_is_counted_again() {
+        let views = ViewCounter::new(Duration::from_millis(0));
+        assert!(views.record(1, 10));
+        assert!(views.record(1, 10));
+        assert_eq!(views.take_pending().get(&10), Some(&2));
+    }
+
+    #[test]
+    fn take_pending_drains_the_counts() {
+        let views = ViewCounter::new(Duration::from_secs(60));
+        views.record(1, 10);
+        assert_eq!(views.take_pending().len(), 1);
+        assert!(views.take_pending().is_empty());
+        // the window still applies after a flush
+        assert!(!views.record(1, 10));
+    }
+
+    #[test]
+    fn restore_adds_back_to_pending() {
+        let views = ViewCounter::new(Duration::from_secs(60));
+        views.record(1, 10);
+        let pending = views.take_pending();
+        views.restore(10, pending[&10]);
+        views.record(2, 10);
+        assert_eq!(views.take_pending().get(&10), Some(&2));
+    }
+
+    #[test]
+    fn views_are_not_counted_past_the_limit() {
+        let views = ViewCounter { max_seen: 2, ..ViewCounter::new(Duration::from_secs(60)) };
+        assert!(views.record(1, 10));
+        assert!(views.record(1, 11));
+        assert!(!views.record(1, 12));
+        assert_eq!(views.take_pending().len(), 2);
+    }
+
+    #[test]
+    fn expired_views_make_room_past_the_limit() {
+        let views = ViewCounter { max_seen: 2, ..ViewCounter::new(Duration::from_millis(0)) };
+        assert!(views.record(1, 10));
+        assert!(views.record(1, 11));
+        assert!(views.record(1, 12));
+    }
+}
@@ -1,6 +1,32 @@
+DROP FUNCTION is_blocked;
+DROP TABLE blocks cascade;
+DROP MATERIALIZED VIEW product_scores;
+DROP FUNCTION is_entitled;
+DROP TABLE tips cascade;
+DROP TABLE subscriptions cascade;
+DROP TABLE subscription_tiers cascade;
+DROP TABLE offers cascade;
+DROP TABLE bids cascade;
+DROP TABLE auctions cascade;
+DROP TABLE sale_prices cascade;
+DROP TABLE discount_codes cascade;
+DROP FUNCTION is_treasury;
+DROP TABLE platform_settings cascade;
+DROP TABLE product_splits cascade;
+DROP TABLE cart_items cascade;
+DROP TABLE purchases cascade;
+DROP TABLE balance_history cascade;
+DROP TABLE order_splits cascade;
+DROP TABLE orders cascade;
+DROP TABLE product_medias cascade;
+DROP TABLE comments cascade;
+DROP TABLE product_likes cascade;
+DROP VIEW message_details;
 DROP TABLE messages cascade;
---DROP TABLE thread_participant cascade ;
---DROP TABLE threads cascade;
+DROP FUNCTION notify_message;
+DROP FUNCTION touch_thread;
+DROP TABLE thread_participant cascade;
+DROP TABLE threads cascade;
 DROP TABLE products cascade;
 DROP TABLE medias cascade;
 DROP TABLE follows cascade;
@@ -19,9 +45,13 @@ CREATE TABLE users (
     username TEXT NOT NULL UNIQUE,
     eth_address TEXT NOT NULL UNIQUE,
     bio TEXT DEFAULT 'Hello, i am new on Squarrin',
-    quadreum BIGINT DEFAULT 1000,
+    -- quadreum amounts are stored in hundredths of a token
+    quadreum BIGINT DEFAULT 100000,
     avatar TEXT DEFAULT '',
-    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    quadreum_escrow BIGINT DEFAULT 0,
+    -- taken from quadreum while the user has the highest bid of an auction
+    quadreum_held BIGINT DEFAULT 0
 );
 
 
@@ -33,6 +63,24 @@ CREATE TABLE follows(
     PRIMARY KEY (followee_id, follower_id)
 );
 
+CREATE TABLE blocks (
+    blocker_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    blocked_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blocker_id, blocked_id),
+    CHECK (blocker_id != blocked_id)
+);
+
+CREATE INDEX blocks_blocked_id ON blocks(blocked_id);
+
+-- two users don't see nor reach each other once one of them blocked the other
+CREATE FUNCTION is_blocked(user_id BIGINT, other_id BIGINT) RETURNS BOOLEAN AS $$
+    SELECT EXISTS(
+        SELECT 1 FROM blocks
+        WHERE (blocks.blocker_id = $1 AND blocks.blocked_id = $2) OR (blocks.blocker_id = $2 AND blocks.blocked_id = $1)
+    )
+$$ LANGUAGE SQL STABLE;
+
 CREATE TABLE medias (
     id BIGSERIAL PRIMARY KEY NOT NULL,
     path TEXT NOT NULL UNIQUE,
@@ -45,20 +93,396 @@ CREATE TABLE products (
     id BIGSERIAL PRIMARY KEY NOT NULL,
     product_type TEXT NOT NULL,
     seller_id BIGINT NOT NULL REFERENCES users(id),
-    buyers_id BIGINT DEFAULT 0,
+    -- units left of a REAL product, NULL for a MEDIA product sold without limit
+    stock BIGINT,
     description TEXT DEFAULT '' NOT NULL,
-    price BIGINT DEFAULT 0,
+    price BIGINT DEFAULT 0 CHECK (price >= 0),
     media_id BIGINT NOT NULL REFERENCES medias(id),
     views BIGINT DEFAULT 0,
     likes BIGINT DEFAULT 0,
+    -- set when the seller removes a product that was already sold
+    deleted_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- every media of a product in display order, products.media_id being the cover
+CREATE TABLE product_medias (
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    media_id BIGINT NOT NULL REFERENCES medias(id),
+    position INT NOT NULL,
+    PRIMARY KEY (product_id, media_id)
+);
+
+CREATE VIEW product_galleries AS
+    SELECT product_medias.product_id,
+    json_agg(json_build_object(
+        'id', medias.id,
+        'path', medias.path,
+        'thumbnail_path', medias.thumbnail_path,
+        'media_type', medias.media_type
+    ) ORDER BY product_medias.position) AS medias
+    FROM product_medias INNER JOIN medias ON product_medias.media_id = medias.id
+    GROUP BY product_medias.product_id;
+
+CREATE TABLE product_likes (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, product_id)
+);
+
+CREATE TABLE comments (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    author_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- NULL for a top level comment, the comment replied to otherwise
+    parent_id BIGINT REFERENCES comments(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    edited_at TIMESTAMPTZ,
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
+CREATE INDEX comments_product_id ON comments(product_id, parent_id, id);
+
+CREATE TABLE purchases (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id),
+    buyer_id BIGINT NOT NULL REFERENCES users(id),
+    price BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX purchases_product_id ON purchases(product_id, buyer_id);
+CREATE INDEX purchases_buyer_id ON purchases(buyer_id);
+
+-- Collaborators sharing the seller's part of each sale of a product
+CREATE TABLE product_splits (
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    bps BIGINT NOT NULL CHECK (bps > 0 AND bps <= 10000),
+    kind TEXT NOT NULL CHECK (kind IN ('REFERRAL', 'CO_CREATOR')),
+    PRIMARY KEY (product_id, user_id)
+);
+
+-- Single row, the fee in basis points taken on every sale and the account receiving it
+CREATE TABLE platform_settings (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    fee_bps BIGINT NOT NULL DEFAULT 0 CHECK (fee_bps >= 0 AND fee_bps <= 10000),
+    treasury_id BIGINT NOT NULL REFERENCES users(id)
+);
+
+-- the treasury only receives fees, it starts empty
+INSERT INTO users (username, eth_address, bio, quadreum) VALUES ('squarrin', '0x0000000000000000000000000000000000000000', 'Squarrin treasury', 0);
+INSERT INTO platform_settings (fee_bps, treasury_id) SELECT 500, id FROM users WHERE username = 'squarrin';
+
+-- the treasury account can't be found, followed, messaged, tipped nor sell anything
+CREATE FUNCTION is_treasury(user_id BIGINT) RETURNS BOOLEAN AS $$
+    SELECT EXISTS(SELECT 1 FROM platform_settings WHERE treasury_id = $1)
+$$ LANGUAGE SQL STABLE;
+
+CREATE TABLE discount_codes (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    seller_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code TEXT NOT NULL UNIQUE,
+    percent_off BIGINT CHECK (percent_off > 0 AND percent_off <= 100),
+    amount_off BIGINT CHECK (amount_off > 0),
+    -- unlimited when NULL
+    max_uses BIGINT,
+    uses BIGINT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ,
+    -- every product of the seller when NULL
+    product_id BIGINT REFERENCES products(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((percent_off IS NULL) != (amount_off IS NULL))
+);
+
+CREATE TABLE sale_prices (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    price BIGINT NOT NULL CHECK (price >= 0),
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (starts_at < ends_at)
+);
+
+CREATE INDEX sale_prices_product_id ON sale_prices(product_id, ends_at);
+
+-- lowest price of the sales running now
+CREATE VIEW active_sales AS
+    SELECT product_id, MIN(price) AS price FROM sale_prices
+    WHERE starts_at <= NOW() AND ends_at > NOW()
+    GROUP BY product_id;
+
+CREATE TABLE auctions (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    starting_price BIGINT NOT NULL CHECK (starting_price >= 0),
+    -- the product is not sold under it
+    reserve_price BIGINT NOT NULL DEFAULT 0,
+    ends_at TIMESTAMPTZ NOT NULL,
+    extension_secs BIGINT NOT NULL DEFAULT 300 CHECK (extension_secs >= 0),
+    status TEXT NOT NULL DEFAULT 'OPEN' CHECK (status IN ('OPEN', 'SOLD', 'UNSOLD')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- a single running auction per product
+CREATE UNIQUE INDEX auctions_open_product_id ON auctions(product_id) WHERE status = 'OPEN';
+
+CREATE TABLE bids (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    auction_id BIGINT NOT NULL REFERENCES auctions(id) ON DELETE CASCADE,
+    bidder_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL,
+    shipping_address TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX bids_auction_id ON bids(auction_id, amount DESC);
+
+-- Price proposed for a product, proposed_by is the buyer for an offer and the seller for a counter-offer
+CREATE TABLE offers (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    buyer_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    seller_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    proposed_by BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    price BIGINT NOT NULL CHECK (price >= 0),
+    status TEXT NOT NULL DEFAULT 'PENDING' CHECK (status IN ('PENDING', 'ACCEPTED', 'DECLINED', 'COUNTERED', 'EXPIRED')),
+    -- the offer this one counters
+    parent_id BIGINT REFERENCES offers(id) ON DELETE CASCADE,
+    shipping_address TEXT,
+    expires_at TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '2 days',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX offers_pending_expires_at ON offers(expires_at) WHERE status = 'PENDING';
+
+CREATE TABLE subscription_tiers (
+    creator_id BIGINT PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- debited every month
+    price BIGINT NOT NULL CHECK (price > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE subscriptions (
+    subscriber_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    creator_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- price of the last payment
+    price BIGINT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'ACTIVE' CHECK (status IN ('ACTIVE', 'PAST_DUE', 'CANCELLED', 'EXPIRED')),
+    current_period_end TIMESTAMPTZ NOT NULL,
+    -- set when a renewal fails, the subscription expires after it
+    grace_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (subscriber_id, creator_id)
+);
+
+CREATE INDEX subscriptions_current_period_end ON subscriptions(current_period_end) WHERE status IN ('ACTIVE', 'PAST_DUE');
+
+-- subscriptions giving access to the MEDIA products of the creator now
+CREATE VIEW active_subscriptions AS
+    SELECT subscriber_id, creator_id FROM subscriptions
+    WHERE status != 'EXPIRED' AND (current_period_end > NOW() OR grace_until > NOW());
+
+-- a user sees the content of a product they sell, bought, get for free or through a subscription to its seller
+-- the pictures of REAL products are public
+CREATE FUNCTION is_entitled(user_id BIGINT, product_id BIGINT) RETURNS BOOLEAN AS $$
+    SELECT EXISTS(
+        SELECT 1 FROM products WHERE products.id = $2 AND (
+            products.product_type = 'REAL' OR products.price = 0 OR products.seller_id = $1
+            OR EXISTS(SELECT 1 FROM purchases WHERE purchases.product_id = products.id AND purchases.buyer_id = $1)
+            OR EXISTS(SELECT 1 FROM active_subscriptions
+                WHERE active_subscriptions.subscriber_id = $1 AND active_subscriptions.creator_id = products.seller_id)
+        )
+    )
+$$ LANGUAGE SQL STABLE;
+
+CREATE TABLE tips (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    sender_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    recipient_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    product_id BIGINT REFERENCES products(id) ON DELETE SET NULL,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX tips_recipient_id ON tips(recipient_id, created_at);
+
+CREATE TABLE cart_items (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    product_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    quantity BIGINT NOT NULL DEFAULT 1,
+    -- price when added, checked again at checkout
+    price BIGINT NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, product_id)
+);
+
+-- Fulfilment of the REAL products
+CREATE TABLE orders (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    product_id BIGINT NOT NULL REFERENCES products(id),
+    buyer_id BIGINT NOT NULL REFERENCES users(id),
+    seller_id BIGINT NOT NULL REFERENCES users(id),
+    -- the purchase removed by a refund
+    purchase_id BIGINT REFERENCES purchases(id) ON DELETE SET NULL,
+    price BIGINT NOT NULL,
+    shipping_address TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'PAID' CHECK (status IN ('PAID', 'SHIPPED', 'DELIVERED', 'DISPUTED', 'REFUNDED')),
+    tracking_number TEXT,
+    escrow TEXT NOT NULL DEFAULT 'HELD' CHECK (escrow IN ('HELD', 'FROZEN', 'RELEASED', 'REFUNDED')),
+    -- the deadline of the order: the escrow is released after it once shipped,
+    -- and refunded after it while the order is not shipped or still disputed
+    release_after TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The splits of the product when the order was made, used when its escrow is released
+CREATE TABLE order_splits (
+    order_id BIGINT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    bps BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    PRIMARY KEY (order_id, user_id)
+);
+
+CREATE TABLE balance_history (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- negative when the user spends quadreum
+    amount BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    product_id BIGINT REFERENCES products(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX balance_history_user_id ON balance_history(user_id, created_at);
+
+-- Ranking scores for the discovery feed, refreshed periodically by the server
+-- trending: engagement decayed by the age of the product (in hours)
+-- top: raw engagement
+CREATE MATERIALIZED VIEW product_scores AS
+    WITH engagement AS (
+        SELECT products.id AS product_id,
+        products.created_at,
+        COALESCE(products.likes, 0) * 2.0
+        + COALESCE(products.views, 0) * 0.1
+        + (SELECT COUNT(*) FROM purchases WHERE purchases.product_id = products.id) * 5.0
+        + LN(1 + (SELECT COUNT(*) FROM follows WHERE follows.followee_id = products.seller_id)) AS points
+        FROM products
+    )
+    SELECT product_id,
+    points / POWER(EXTRACT(EPOCH FROM (NOW() - created_at)) / 3600 + 2, 1.5) AS trending,
+    points AS top
+    FROM engagement;
+
+CREATE UNIQUE INDEX product_scores_product_id ON product_scores(product_id);
+
+
+-- Conversations, user_low and user_high are the two users of a direct thread, NULL for a group chat
+CREATE TABLE threads (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    title TEXT,
+    user_low BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    user_high BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    -- kept up to date by touch_thread to list the threads without reading their messages
+    last_message_id BIGINT,
+    last_message_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_low, user_high),
+    CHECK (user_low < user_high)
+);
+
+CREATE TABLE thread_participant (
+    thread_id BIGINT NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- the messages after it are unread
+    last_read_message_id BIGINT NOT NULL DEFAULT 0,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- the thread is hidden from the list of the user until a message is sent after it
+    hidden_at TIMESTAMPTZ,
+    -- a thread started by a user the participant doesn't follow is a message request until they accept or reply
+    accepted BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (thread_id, user_id)
+);
+
+CREATE INDEX thread_participant_user_id ON thread_participant(user_id);
 
 CREATE TABLE messages (
     id BIGSERIAL PRIMARY KEY NOT NULL,
+    thread_id BIGINT NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
     sender BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-    receiver BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- the text, or the caption of an attachment
     content TEXT NOT NULL,
-    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    kind TEXT NOT NULL DEFAULT 'TEXT' CHECK (kind IN ('TEXT', 'MEDIA', 'PRODUCT')),
+    media_id BIGINT REFERENCES medias(id),
+    -- NULL once the shared product is deleted
+    product_id BIGINT REFERENCES products(id) ON DELETE SET NULL,
+    edited_at TIMESTAMPTZ,
+    -- a deleted message is kept as a tombstone without its content
+    deleted_at TIMESTAMPTZ,
+    -- when a recipient first read the message, NULL while it is unread
+    read_at TIMESTAMPTZ,
+    CHECK ((kind = 'MEDIA') = (media_id IS NOT NULL))
 );
+
+CREATE INDEX messages_thread_id ON messages(thread_id, id);
+
+-- messages with their attachment and the card of their shared product
+-- the cover of a shared product is only shown when it is not a paid content
+CREATE VIEW message_details AS
+    SELECT messages.id, messages.thread_id, messages.sender, messages.content, messages.created_at,
+    CASE WHEN messages.deleted_at IS NULL THEN messages.kind ELSE 'DELETED' END AS kind,
+    messages.edited_at, messages.read_at,
+    CASE WHEN medias.id IS NULL THEN NULL ELSE json_build_object(
+        'id', medias.id,
+        'path', medias.path,
+        'thumbnail_path', medias.thumbnail_path,
+        'media_type', medias.media_type,
+        'created_at', medias.created_at
+    ) END AS media,
+    CASE WHEN products.id IS NULL THEN NULL ELSE json_build_object(
+        'id', products.id,
+        'seller_id', products.seller_id,
+        'description', products.description,
+        'product_type', products.product_type,
+        'price', products.price,
+        'cover_path', CASE WHEN products.product_type = 'REAL' OR products.price = 0 THEN covers.path ELSE covers.thumbnail_path END
+    ) END AS product
+    FROM messages
+    LEFT JOIN medias ON medias.id = messages.media_id
+    LEFT JOIN products ON products.id = messages.product_id
+    LEFT JOIN medias covers ON covers.id = products.media_id;
+
+CREATE FUNCTION touch_thread() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE threads SET last_message_id = NEW.id, last_message_at = NEW.created_at WHERE id = NEW.thread_id;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_touch_thread AFTER INSERT ON messages
+    FOR EACH ROW EXECUTE PROCEDURE touch_thread();
+
+-- push every new, edited or deleted message to the server instances, they load it and forward it to the participants connected
+-- only the id is sent, a payload is limited to 8000 bytes
+-- nothing is pushed in a direct thread where one user blocked the other
+CREATE FUNCTION notify_message() RETURNS TRIGGER AS $$
+BEGIN
+    IF NOT EXISTS(
+        SELECT 1 FROM threads WHERE id = NEW.thread_id AND user_low IS NOT NULL AND is_blocked(user_low, user_high)
+    ) THEN
+        PERFORM pg_notify('swell_events', json_build_object('message_id', NEW.id)::text);
+    END IF;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_notify AFTER INSERT OR UPDATE OF content, deleted_at ON messages
+    FOR EACH ROW EXECUTE PROCEDURE notify_message();